@@ -0,0 +1,257 @@
+use std::rc::Rc;
+
+use crate::stack::Stack;
+use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
+use crate::numeric::Numeric;
+use crate::value::Value;
+
+// Arrays are copied on write like every value (see `Value`), so share one by keeping it in a ptr.
+// Every op except `Pack` and `Unpack` leaves the array where it was and pushes its results above
+// it. Indexes and lengths are `usize`.
+// The most items `New` reserves room for. The capacity is only a hint, and the first copy of an
+// array that gets changed drops it anyway.
+const MAX_CAPACITY: usize = 1 << 16;
+
+
+#[derive(Debug, Clone)]
+pub enum ArrayOp {
+    // capacity -> an empty array
+    New,
+    // array, value -> array
+    Push,
+    // array -> array, its last item
+    Pop,
+    // array, index -> array, item
+    Get,
+    // array, index, value -> array
+    Set,
+    // array -> array, length
+    Len,
+    // array, start, end -> array, the items from start up to but not including end
+    Slice,
+    // items..., count -> an array of the count items, the bottom one first
+    Pack,
+    // array -> every item, then the item count
+    Unpack
+}
+
+
+fn index(value: &Value) -> Result<usize, InstructionError> {
+    match value {
+        Value::Numeric(Numeric::USize(index)) => Ok(*index),
+        _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type usize"))
+    }
+}
+
+
+fn size(value: usize) -> Value {
+    Value::Numeric(Numeric::USize(value))
+}
+
+
+fn array(value: &mut Value) -> Result<&mut Rc<Vec<Value>>, InstructionError> {
+    match value {
+        Value::Array(items) => Ok(items),
+        _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type array"))
+    }
+}
+
+
+fn out_of_bounds(index: usize, len: usize) -> InstructionError {
+    InstructionError::new(ErrorKind::OutOfRange, &format!("Index {} is out of bounds for an array of length {}", index, len))
+}
+
+
+impl ArrayOp {
+    fn arity(&self, current_stack: &[Value]) -> Result<usize, InstructionError> {
+        Ok(match self {
+            ArrayOp::New | ArrayOp::Pop | ArrayOp::Len | ArrayOp::Unpack => 1,
+            ArrayOp::Push | ArrayOp::Get => 2,
+            ArrayOp::Set | ArrayOp::Slice => 3,
+            ArrayOp::Pack => match current_stack.last() {
+                Some(count) => index(count)?.saturating_add(1),
+                None => 1
+            }
+        })
+    }
+
+    // Everything is checked before the array is changed, so a failed op can put its operands back.
+    fn evaluate(&self, operands: &mut [Value]) -> Result<Vec<Value>, InstructionError> {
+        if let ArrayOp::New = self {
+            let capacity = index(&operands[0])?;
+
+            if capacity > MAX_CAPACITY {
+                let message = format!("Array capacity {} is above the limit of {}", capacity, MAX_CAPACITY);
+                return Err(InstructionError::new(ErrorKind::OutOfRange, &message));
+            }
+
+            return Ok(vec![Value::Array(Rc::new(Vec::with_capacity(capacity)))]);
+        }
+
+        if let ArrayOp::Pack = self {
+            let items = operands[..operands.len() - 1].to_vec();
+
+            return Ok(vec![Value::Array(Rc::new(items))]);
+        }
+
+        let (head, rest) = operands.split_first_mut().unwrap();
+        let items = array(head)?;
+        let len = items.len();
+
+        let results = match self {
+            ArrayOp::Push => {
+                Rc::make_mut(items).push(rest[0].clone());
+                vec![]
+            },
+            ArrayOp::Pop => match Rc::make_mut(items).pop() {
+                Some(item) => vec![item],
+                None => return Err(InstructionError::new(ErrorKind::OutOfRange, "Can't pop from an empty array"))
+            },
+            ArrayOp::Get => {
+                let at = index(&rest[0])?;
+                vec![items.get(at).cloned().ok_or_else(|| out_of_bounds(at, len))?]
+            },
+            ArrayOp::Set => {
+                let at = index(&rest[0])?;
+
+                if at >= len {
+                    return Err(out_of_bounds(at, len));
+                }
+
+                Rc::make_mut(items)[at] = rest[1].clone();
+                vec![]
+            },
+            ArrayOp::Len => vec![size(len)],
+            ArrayOp::Slice => {
+                let (start, end) = (index(&rest[0])?, index(&rest[1])?);
+
+                if start > end || end > len {
+                    let message = format!("Slice {}..{} is out of bounds for an array of length {}", start, end, len);
+                    return Err(InstructionError::new(ErrorKind::OutOfRange, &message));
+                }
+
+                vec![Value::Array(Rc::new(items[start..end].to_vec()))]
+            },
+            ArrayOp::Unpack => {
+                let mut results = items.to_vec();
+                results.push(size(len));
+
+                return Ok(results);
+            },
+            ArrayOp::New | ArrayOp::Pack => unreachable!()
+        };
+
+        Ok([head.clone()].into_iter().chain(results).collect())
+    }
+}
+
+
+impl Runnable for ArrayOp {
+    fn run(&self, stack: &mut Stack) -> InstructionResult {
+        let current_stack = stack.current();
+
+        let mut current_stack = current_stack.borrow_mut();
+
+        let arity = match self.arity(&current_stack) {
+            Ok(arity) => arity,
+            Err(error) => return InstructionResult::Error(error)
+        };
+
+        if current_stack.len() < arity {
+            let message = format!("Array operation needs {} operand(s) on the stack", arity);
+            return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, &message));
+        }
+
+        // Taking the operands off the stack lets an array that isn't shared be changed in place.
+        let start = current_stack.len() - arity;
+        let mut operands = current_stack.split_off(start);
+
+        match self.evaluate(&mut operands) {
+            Ok(results) => {
+                current_stack.extend(results);
+
+                InstructionResult::None
+            },
+            Err(error) => {
+                current_stack.extend(operands);
+
+                InstructionResult::Error(error)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::testing::{run, push, contents};
+
+    #[test]
+    fn copies_are_independent() {
+        let mut stack = Stack::new();
+
+        push(&mut stack, size(2));
+        run(&mut stack, ArrayOp::New).unwrap();
+        push(&mut stack, Value::Bool(true));
+        run(&mut stack, ArrayOp::Push).unwrap();
+
+        let copy = stack.current().borrow().last().cloned().unwrap();
+        push(&mut stack, Value::Bool(false));
+        run(&mut stack, ArrayOp::Push).unwrap();
+
+        assert_eq!(copy.to_string(), "[true]");
+        assert_eq!(contents(&stack), ["[true, false]"]);
+    }
+
+    #[test]
+    fn capacity_is_capped() {
+        let mut stack = Stack::new();
+
+        push(&mut stack, size(MAX_CAPACITY));
+        run(&mut stack, ArrayOp::New).unwrap();
+
+        push(&mut stack, size(MAX_CAPACITY + 1));
+        assert_eq!(run(&mut stack, ArrayOp::New), Err(ErrorKind::OutOfRange));
+
+        push(&mut stack, size(usize::MAX));
+        assert_eq!(run(&mut stack, ArrayOp::New), Err(ErrorKind::OutOfRange));
+        assert_eq!(contents(&stack).len(), 3);
+    }
+
+    #[test]
+    fn unshared_arrays_change_in_place() {
+        let mut stack = Stack::new();
+
+        push(&mut stack, Value::Array(Rc::new(vec![size(1)])));
+        let address = |stack: &Stack| match stack.current().borrow().last() {
+            Some(Value::Array(items)) => Rc::as_ptr(items),
+            _ => panic!("expected an array")
+        };
+
+        let before = address(&stack);
+        push(&mut stack, size(0));
+        push(&mut stack, size(2));
+        run(&mut stack, ArrayOp::Set).unwrap();
+
+        assert_eq!(address(&stack), before);
+        assert_eq!(contents(&stack), ["[2usize]"]);
+    }
+
+    #[test]
+    fn failures_leave_the_operands() {
+        let mut stack = Stack::new();
+
+        push(&mut stack, Value::Array(Rc::new(vec![size(1)])));
+        push(&mut stack, size(1));
+        push(&mut stack, size(2));
+
+        assert_eq!(run(&mut stack, ArrayOp::Set), Err(ErrorKind::OutOfRange));
+        assert_eq!(contents(&stack), ["[1usize]", "1usize", "2usize"]);
+
+        assert_eq!(run(&mut stack, ArrayOp::Slice), Err(ErrorKind::OutOfRange));
+        assert_eq!(run(&mut stack, ArrayOp::Pop), Err(ErrorKind::TypeMismatch));
+        assert_eq!(contents(&stack), ["[1usize]", "1usize", "2usize"]);
+    }
+}
@@ -0,0 +1,1131 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::numeric::{Numeric, NumericType};
+use crate::value::{Value, ValueType};
+use crate::data_type::DataType;
+use crate::ptr::Ptr;
+use crate::instruction::Instruction;
+use crate::stack_op::StackOp;
+use crate::math_op::MathOp;
+use crate::type_op::TypeOp;
+use crate::str_op::StrOp;
+use crate::logic_op::LogicOp;
+use crate::array_op::ArrayOp;
+use crate::map_op::MapOp;
+use crate::struct_op::StructOp;
+use crate::struct_def::{StructDef, Field};
+use crate::control_op::ControlOp;
+use crate::control::JumpTarget;
+use crate::function::Function;
+use crate::program::Program;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize
+}
+
+
+#[derive(Debug)]
+pub struct AsmError {
+    pub position: Position,
+    pub message: String
+}
+
+
+impl AsmError {
+    pub fn new(position: Position, message: &str) -> AsmError {
+        AsmError {
+            position,
+            message: message.to_string()
+        }
+    }
+}
+
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.position.line, self.position.column, self.message)
+    }
+}
+
+
+impl std::error::Error for AsmError {}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(String),
+    Str(String),
+    PtrRef(String),
+    Deref(String),
+    TypeTag(String),
+    Local(String),
+    Comma,
+    Colon,
+    Equals,
+    LBracket,
+    RBracket,
+}
+
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: Position
+}
+
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+
+fn take_while(chars: &[char], start: usize, predicate: fn(char) -> bool) -> String {
+    chars[start..].iter().take_while(|c| predicate(**c)).collect()
+}
+
+
+fn lex_number(chars: &[char], start: usize) -> String {
+    let mut end = start + 1;
+
+    while end < chars.len() {
+        let c = chars[end];
+        let exponent_sign = (c == '+' || c == '-') && matches!(chars[end - 1], 'e' | 'E');
+
+        if c.is_ascii_alphanumeric() || c == '_' || c == '.' || exponent_sign {
+            end += 1;
+        } else {
+            break;
+        }
+    }
+
+    chars[start..end].iter().collect()
+}
+
+
+fn lex_string(chars: &[char], start: usize, line: usize) -> Result<(String, usize), AsmError> {
+    let mut string = String::new();
+    let mut i = start + 1;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let position = Position { line, column: i + 1 };
+
+        match c {
+            '"' => return Ok((string, i + 1)),
+            '\\' => {
+                let escaped = chars.get(i + 1)
+                    .ok_or_else(|| AsmError::new(position, "Unterminated escape sequence"))?;
+
+                match escaped {
+                    'n'  => string.push('\n'),
+                    't'  => string.push('\t'),
+                    'r'  => string.push('\r'),
+                    '0'  => string.push('\0'),
+                    '\\' => string.push('\\'),
+                    '"'  => string.push('"'),
+                    '\'' => string.push('\''),
+                    'u'  => {
+                        if chars.get(i + 2) != Some(&'{') {
+                            return Err(AsmError::new(position, "Expected '{' after '\\u'"));
+                        }
+
+                        let digits = take_while(chars, i + 3, |c| c.is_ascii_hexdigit());
+
+                        if chars.get(i + 3 + digits.len()) != Some(&'}') {
+                            return Err(AsmError::new(position, "Expected '}' to close unicode escape"));
+                        }
+
+                        let c = u32::from_str_radix(&digits, 16).ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| AsmError::new(position, "Invalid unicode escape"))?;
+
+                        string.push(c);
+                        i += 2 + digits.len();
+                    },
+                    other => {
+                        return Err(AsmError::new(position, &format!("Unknown escape sequence '\\{}'", other)));
+                    }
+                }
+
+                i += 2;
+            },
+            _ => {
+                string.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    Err(AsmError::new(Position { line, column: start + 1 }, "Unterminated string literal"))
+}
+
+
+fn lex_line(line: &str, line_number: usize) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let position = Position { line: line_number, column: i + 1 };
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == ';' {
+            break;
+        }
+
+        let kind = match c {
+            ',' => { i += 1; TokenKind::Comma },
+            ':' => { i += 1; TokenKind::Colon },
+            '=' => { i += 1; TokenKind::Equals },
+            '[' => { i += 1; TokenKind::LBracket },
+            ']' => { i += 1; TokenKind::RBracket },
+            '"' => {
+                let (string, end) = lex_string(&chars, i, line_number)?;
+                i = end;
+
+                TokenKind::Str(string)
+            },
+            '@' | '*' => {
+                let name = take_while(&chars, i + 1, is_ident_char);
+
+                if name.is_empty() {
+                    return Err(AsmError::new(position, &format!("Expected ptr name after '{}'", c)));
+                }
+
+                i += 1 + name.len();
+
+                if c == '@' { TokenKind::PtrRef(name) } else { TokenKind::Deref(name) }
+            },
+            '#' => {
+                let name = take_while(&chars, i + 1, is_ident_char);
+
+                if name.is_empty() {
+                    return Err(AsmError::new(position, "Expected type name after '#'"));
+                }
+
+                i += 1 + name.len();
+
+                TokenKind::TypeTag(name)
+            },
+            '$' => {
+                let index = take_while(&chars, i + 1, |c| c.is_ascii_digit());
+
+                if index.is_empty() {
+                    return Err(AsmError::new(position, "Expected local index after '$'"));
+                }
+
+                i += 1 + index.len();
+
+                TokenKind::Local(index)
+            },
+            c if c.is_ascii_digit()
+                || (c == '-' && chars.get(i + 1).is_some_and(|c| c.is_ascii_alphanumeric()))
+                || (c == '+' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) => {
+                let number = lex_number(&chars, i);
+                i += number.len();
+
+                TokenKind::Number(number)
+            },
+            c if is_ident_start(c) => {
+                let ident = take_while(&chars, i, is_ident_char);
+                i += ident.len();
+
+                TokenKind::Ident(ident)
+            },
+            _ => {
+                return Err(AsmError::new(position, &format!("Unexpected character '{}'", c)));
+            }
+        };
+
+        tokens.push(Token { kind, position });
+    }
+
+    Ok(tokens)
+}
+
+
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Ident(ident) => format!("'{}'", ident),
+        TokenKind::Number(number) => format!("'{}'", number),
+        TokenKind::Str(_) => "string literal".to_string(),
+        TokenKind::PtrRef(name) => format!("'@{}'", name),
+        TokenKind::Deref(name) => format!("'*{}'", name),
+        TokenKind::TypeTag(name) => format!("'#{}'", name),
+        TokenKind::Local(index) => format!("'${}'", index),
+        TokenKind::Comma => "','".to_string(),
+        TokenKind::Colon => "':'".to_string(),
+        TokenKind::Equals => "'='".to_string(),
+        TokenKind::LBracket => "'['".to_string(),
+        TokenKind::RBracket => "']'".to_string(),
+    }
+}
+
+
+pub fn parse_numeric(text: &str) -> Option<Numeric> {
+    let text = text.replace('_', "");
+
+    for suffix in ["u128", "u16", "u32", "u64", "u8", "i128", "i16", "i32", "i64", "i8", "f32", "f64", "usize", "isize"] {
+        if let Some(digits) = text.strip_suffix(suffix) {
+            if digits.is_empty() {
+                return None;
+            }
+
+            return NumericType::from_suffix(suffix).and_then(|to| to.parse(digits));
+        }
+    }
+
+    if text.contains(['.', 'e', 'E']) || text.contains("inf") || text.contains("NaN") {
+        NumericType::Float64.parse(&text)
+    } else {
+        NumericType::Int32.parse(&text)
+    }
+}
+
+
+struct Line {
+    number: usize,
+    length: usize,
+    tokens: Vec<Token>
+}
+
+
+impl Line {
+    // A `name:` line marks the position of the next instruction as a jump target.
+    fn jump_label(&self) -> Option<&str> {
+        match self.tokens.as_slice() {
+            [Token { kind: TokenKind::Ident(name), .. }, Token { kind: TokenKind::Colon, .. }] => Some(name),
+            _ => None
+        }
+    }
+}
+
+
+struct Cursor<'a> {
+    line: &'a Line,
+    index: usize
+}
+
+
+impl<'a> Cursor<'a> {
+    fn new(line: &'a Line) -> Cursor<'a> {
+        Cursor { line, index: 0 }
+    }
+
+    fn end_position(&self) -> Position {
+        Position { line: self.line.number, column: self.line.length + 1 }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.line.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.line.tokens.get(self.index);
+        self.index += 1;
+        token
+    }
+
+    fn expect_next(&mut self, what: &str) -> Result<&'a Token, AsmError> {
+        let position = self.end_position();
+
+        self.next().ok_or_else(|| AsmError::new(position, &format!("Expected {}, found end of line", what)))
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<&'a Token, AsmError> {
+        let expected = describe(&kind);
+        let token = self.expect_next(&expected)?;
+
+        if token.kind == kind {
+            Ok(token)
+        } else {
+            Err(AsmError::new(token.position, &format!("Expected {}, found {}", expected, describe(&token.kind))))
+        }
+    }
+
+    fn expect_ident(&mut self, what: &str) -> Result<(&'a str, Position), AsmError> {
+        let token = self.expect_next(what)?;
+
+        if let TokenKind::Ident(ident) = &token.kind {
+            Ok((ident, token.position))
+        } else {
+            Err(AsmError::new(token.position, &format!("Expected {}, found {}", what, describe(&token.kind))))
+        }
+    }
+
+    fn finish(&self) -> Result<(), AsmError> {
+        if let Some(token) = self.peek() {
+            Err(AsmError::new(token.position, &format!("Unexpected {}", describe(&token.kind))))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+
+struct PendingFunction {
+    name: String,
+    position: Position,
+    function: Function
+}
+
+
+struct Assembler {
+    labels: HashMap<String, usize>,
+    structs: HashMap<String, Rc<StructDef>>,
+    ptrs: Vec<(String, Ptr)>,
+    exports: HashSet<String>,
+    reserved_ids: HashSet<usize>,
+    jump_labels: HashMap<String, usize>
+}
+
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            labels: HashMap::new(),
+            structs: HashMap::new(),
+            ptrs: vec![],
+            exports: HashSet::new(),
+            reserved_ids: HashSet::new(),
+            jump_labels: HashMap::new()
+        }
+    }
+
+    // Starts from an existing program's names so new source can refer to them and redefine them.
+    fn from_program(base: &Program) -> Assembler {
+        Assembler {
+            labels: base.labels.clone(),
+            structs: base.structs.clone(),
+            ptrs: base.ptrs.clone(),
+            exports: base.exports.clone(),
+            reserved_ids: base.functions.keys().chain(base.labels.values()).copied().collect(),
+            jump_labels: HashMap::new()
+        }
+    }
+
+    fn ptr(&self, name: &str, position: Position) -> Result<Ptr, AsmError> {
+        self.ptrs.iter()
+            .find(|(ptr_name, _)| ptr_name == name)
+            .map(|(_, ptr)| ptr.clone())
+            .ok_or_else(|| AsmError::new(position, &format!("Unknown ptr '{}'", name)))
+    }
+
+    fn usize_attribute(&self, token: &Token) -> Result<usize, AsmError> {
+        if let TokenKind::Number(number) = &token.kind {
+            let digits = number.strip_suffix("usize").unwrap_or(number);
+
+            if let Ok(value) = digits.replace('_', "").parse() {
+                return Ok(value);
+            }
+        }
+
+        Err(AsmError::new(token.position, &format!("Expected unsigned integer, found {}", describe(&token.kind))))
+    }
+
+    fn value(&self, token: &Token) -> Result<Value, AsmError> {
+        match &token.kind {
+            TokenKind::Number(number) => {
+                parse_numeric(number)
+                    .map(Value::Numeric)
+                    .ok_or_else(|| AsmError::new(token.position, &format!("Invalid numeric literal '{}'", number)))
+            },
+            TokenKind::Str(string) => Ok(Value::Str(string.clone())),
+            TokenKind::Ident(ident) if ident == "true" => Ok(Value::Bool(true)),
+            TokenKind::Ident(ident) if ident == "false" => Ok(Value::Bool(false)),
+            TokenKind::Ident(ident) if ident.starts_with("inf") || ident.starts_with("NaN") => {
+                parse_numeric(ident)
+                    .map(Value::Numeric)
+                    .ok_or_else(|| AsmError::new(token.position, &format!("Invalid numeric literal '{}'", ident)))
+            },
+            TokenKind::PtrRef(name) => Ok(Value::Ptr(self.ptr(name, token.position)?)),
+            TokenKind::TypeTag(name) => {
+                DataType::from_name(name)
+                    .map(Value::Type)
+                    .ok_or_else(|| AsmError::new(token.position, &format!("Unknown type '{}'", name)))
+            },
+            other => Err(AsmError::new(token.position, &format!("Expected value, found {}", describe(other))))
+        }
+    }
+
+    fn value_type(&self, token: &Token) -> Result<ValueType, AsmError> {
+        match &token.kind {
+            TokenKind::Ident(ident) if ident == "stack" => Ok(ValueType::StackValue),
+            TokenKind::Ident(ident) if self.labels.contains_key(ident) => {
+                Ok(ValueType::Value(Value::Numeric(Numeric::USize(self.labels[ident]))))
+            },
+            TokenKind::Deref(name) => Ok(ValueType::Ptr(self.ptr(name, token.position)?)),
+            TokenKind::Local(_) => self.local_operand(token).map(ValueType::Local),
+            _ => self.value(token).map_err(|error| {
+                if let TokenKind::Ident(ident) = &token.kind {
+                    AsmError::new(token.position, &format!("Unknown function or value '{}'", ident))
+                } else {
+                    error
+                }
+            }).map(ValueType::Value)
+        }
+    }
+
+    fn local_operand(&self, token: &Token) -> Result<usize, AsmError> {
+        match &token.kind {
+            TokenKind::Local(index) => index.parse()
+                .map_err(|_| AsmError::new(token.position, &format!("Local index '${}' is too large", index))),
+            other => Err(AsmError::new(token.position, &format!("Expected local '$N', found {}", describe(other))))
+        }
+    }
+
+    fn ptr_operand(&self, token: &Token) -> Result<Ptr, AsmError> {
+        if let TokenKind::PtrRef(name) = &token.kind {
+            self.ptr(name, token.position)
+        } else {
+            Err(AsmError::new(token.position, &format!("Expected ptr '@name', found {}", describe(&token.kind))))
+        }
+    }
+
+    fn numeric_type_operand(&self, token: &Token) -> Result<NumericType, AsmError> {
+        if let TokenKind::Ident(ident) = &token.kind {
+            if let Some(to) = NumericType::from_suffix(ident) {
+                return Ok(to);
+            }
+        }
+
+        Err(AsmError::new(token.position, &format!("Expected numeric type, found {}", describe(&token.kind))))
+    }
+
+    fn data_type_operand(&self, token: &Token) -> Result<DataType, AsmError> {
+        if let TokenKind::Ident(ident) = &token.kind {
+            if let Some(data_type) = DataType::from_name(ident) {
+                return Ok(data_type);
+            }
+        }
+
+        Err(AsmError::new(token.position, &format!("Expected type, found {}", describe(&token.kind))))
+    }
+
+    fn struct_operand(&self, token: &Token) -> Result<String, AsmError> {
+        match &token.kind {
+            TokenKind::Ident(name) if self.structs.contains_key(name) => Ok(name.clone()),
+            TokenKind::Ident(name) => Err(AsmError::new(token.position, &format!("Unknown struct '{}'", name))),
+            other => Err(AsmError::new(token.position, &format!("Expected struct name, found {}", describe(other))))
+        }
+    }
+
+    // A field name, or its index in declaration order.
+    fn field_operand(&self, token: &Token) -> Result<Field, AsmError> {
+        match &token.kind {
+            TokenKind::Ident(name) => Ok(Field::Name(name.clone())),
+            _ => self.usize_attribute(token).map(Field::Index)
+        }
+    }
+
+    // A jump label, an absolute instruction index, or a signed offset such as `+2` or `-3`.
+    fn jump_target_operand(&self, token: &Token) -> Result<JumpTarget, AsmError> {
+        match &token.kind {
+            TokenKind::Ident(ident) => {
+                self.jump_labels.get(ident)
+                    .map(|index| JumpTarget::Absolute(*index))
+                    .ok_or_else(|| AsmError::new(token.position, &format!("Unknown jump label '{}'", ident)))
+            },
+            TokenKind::Number(number) => {
+                let digits = number.replace('_', "");
+
+                let target = if digits.starts_with(['+', '-']) {
+                    digits.parse().ok().map(JumpTarget::Relative)
+                } else {
+                    digits.parse().ok().map(JumpTarget::Absolute)
+                };
+
+                target.ok_or_else(|| AsmError::new(token.position, &format!("Invalid jump target '{}'", number)))
+            },
+            other => Err(AsmError::new(token.position, &format!("Expected jump target, found {}", describe(other))))
+        }
+    }
+
+    fn value_list(&self, cursor: &mut Cursor) -> Result<Vec<Value>, AsmError> {
+        cursor.expect(TokenKind::LBracket)?;
+
+        let mut values = vec![];
+
+        loop {
+            let token = cursor.expect_next("value or ']'")?;
+
+            if token.kind == TokenKind::RBracket && values.is_empty() {
+                return Ok(values);
+            }
+
+            values.push(self.value(token)?);
+
+            let token = cursor.expect_next("',' or ']'")?;
+
+            match token.kind {
+                TokenKind::Comma => continue,
+                TokenKind::RBracket => return Ok(values),
+                _ => return Err(AsmError::new(token.position, &format!("Expected ',' or ']', found {}", describe(&token.kind))))
+            }
+        }
+    }
+
+    fn operands<'a>(&self, cursor: &mut Cursor<'a>) -> Result<Vec<&'a Token>, AsmError> {
+        let mut operands = vec![];
+
+        if cursor.peek().is_none() {
+            return Ok(operands);
+        }
+
+        loop {
+            let token = cursor.expect_next("operand")?;
+
+            if token.kind == TokenKind::Comma {
+                return Err(AsmError::new(token.position, "Expected operand, found ','"));
+            }
+
+            operands.push(token);
+
+            if cursor.peek().is_none() {
+                return Ok(operands);
+            }
+
+            cursor.expect(TokenKind::Comma)?;
+        }
+    }
+
+    fn instruction(&self, cursor: &mut Cursor) -> Result<Instruction, AsmError> {
+        let (mnemonic, position) = cursor.expect_ident("instruction")?;
+        let operands = self.operands(cursor)?;
+
+        let arity = |count: usize| {
+            if operands.len() == count {
+                Ok(())
+            } else {
+                Err(AsmError::new(position, &format!("'{}' takes {} operand(s), found {}", mnemonic, count, operands.len())))
+            }
+        };
+
+        let instruction = match mnemonic {
+            "swap"     => { arity(0)?; Instruction::Stack(StackOp::Swap) },
+            "dup"      => { arity(0)?; Instruction::Stack(StackOp::Duplicate) },
+            "drop"     => { arity(0)?; Instruction::Stack(StackOp::Drop) },
+            "pop"      => { arity(1)?; Instruction::Stack(StackOp::Pop(self.ptr_operand(operands[0])?)) },
+            "push"     => { arity(1)?; Instruction::Stack(StackOp::Push(self.value_type(operands[0])?)) },
+            "pushptr"  => { arity(1)?; Instruction::Stack(StackOp::PushPtr(self.ptr_operand(operands[0])?)) },
+            "deref"    => { arity(0)?; Instruction::Stack(StackOp::DeRef) },
+            "substack" => { arity(1)?; Instruction::Stack(StackOp::SubStack(self.value_type(operands[0])?)) },
+            "destack"  => { arity(1)?; Instruction::Stack(StackOp::Destack(self.value_type(operands[0])?)) },
+            "len"      => { arity(0)?; Instruction::Stack(StackOp::Len) },
+            "inspect"  => { arity(0)?; Instruction::Stack(StackOp::Inspect) },
+            "alloc"    => { arity(0)?; Instruction::Stack(StackOp::Alloc) },
+            "store"    => { arity(0)?; Instruction::Stack(StackOp::Store) },
+            "storelocal" => { arity(1)?; Instruction::Stack(StackOp::StoreLocal(self.local_operand(operands[0])?)) },
+            // `deref` under the name that pairs with `store`.
+            "load"     => { arity(0)?; Instruction::Stack(StackOp::DeRef) },
+            "add"      => { arity(0)?; Instruction::Math(MathOp::Add) },
+            "sub"      => { arity(0)?; Instruction::Math(MathOp::Sub) },
+            "mul"      => { arity(0)?; Instruction::Math(MathOp::Mul) },
+            "div"      => { arity(0)?; Instruction::Math(MathOp::Div) },
+            "gt"       => { arity(0)?; Instruction::Math(MathOp::GreaterThan) },
+            "lt"       => { arity(0)?; Instruction::Math(MathOp::LessThan) },
+            "gte"      => { arity(0)?; Instruction::Math(MathOp::GreaterThanEq) },
+            "lte"      => { arity(0)?; Instruction::Math(MathOp::LessThanEq) },
+            "eq"       => { arity(0)?; Instruction::Math(MathOp::Eql) },
+            "rem"      => { arity(0)?; Instruction::Math(MathOp::Rem) },
+            "neg"      => { arity(0)?; Instruction::Math(MathOp::Neg) },
+            "abs"      => { arity(0)?; Instruction::Math(MathOp::Abs) },
+            "band"     => { arity(0)?; Instruction::Math(MathOp::BitAnd) },
+            "bor"      => { arity(0)?; Instruction::Math(MathOp::BitOr) },
+            "bxor"     => { arity(0)?; Instruction::Math(MathOp::BitXor) },
+            "bnot"     => { arity(0)?; Instruction::Math(MathOp::Not) },
+            "shl"      => { arity(0)?; Instruction::Math(MathOp::Shl) },
+            "shr"      => { arity(0)?; Instruction::Math(MathOp::Shr) },
+            "rotl"     => { arity(0)?; Instruction::Math(MathOp::RotateLeft) },
+            "rotr"     => { arity(0)?; Instruction::Math(MathOp::RotateRight) },
+            "min"      => { arity(0)?; Instruction::Math(MathOp::Min) },
+            "max"      => { arity(0)?; Instruction::Math(MathOp::Max) },
+            "pow"      => { arity(0)?; Instruction::Math(MathOp::Pow) },
+            "add.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingAdd) },
+            "sub.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingSub) },
+            "mul.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingMul) },
+            "div.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingDiv) },
+            "rem.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingRem) },
+            "neg.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingNeg) },
+            "abs.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingAbs) },
+            "pow.wrap" => { arity(0)?; Instruction::Math(MathOp::WrappingPow) },
+            "add.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingAdd) },
+            "sub.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingSub) },
+            "mul.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingMul) },
+            "div.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingDiv) },
+            "rem.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingRem) },
+            "neg.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingNeg) },
+            "abs.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingAbs) },
+            "pow.sat"  => { arity(0)?; Instruction::Math(MathOp::SaturatingPow) },
+            "cast"     => { arity(1)?; Instruction::Type(TypeOp::NumericCast(self.numeric_type_operand(operands[0])?)) },
+            "cast.checked" => { arity(1)?; Instruction::Type(TypeOp::CheckedCast(self.numeric_type_operand(operands[0])?)) },
+            "cast.sat" => { arity(1)?; Instruction::Type(TypeOp::SaturatingCast(self.numeric_type_operand(operands[0])?)) },
+            "typeof"   => { arity(0)?; Instruction::Type(TypeOp::TypeOf) },
+            "is"       => { arity(1)?; Instruction::Type(TypeOp::Is(self.data_type_operand(operands[0])?)) },
+            "str.concat" => { arity(0)?; Instruction::Str(StrOp::Concat) },
+            "str.len"    => { arity(0)?; Instruction::Str(StrOp::Len) },
+            "str.chars"  => { arity(0)?; Instruction::Str(StrOp::CharLen) },
+            "str.sub"    => { arity(0)?; Instruction::Str(StrOp::Substring) },
+            "str.find"   => { arity(0)?; Instruction::Str(StrOp::IndexOf) },
+            "str.split"  => { arity(0)?; Instruction::Str(StrOp::Split) },
+            "str.join"   => { arity(0)?; Instruction::Str(StrOp::Join) },
+            "str.trim"   => { arity(0)?; Instruction::Str(StrOp::Trim) },
+            "str.upper"  => { arity(0)?; Instruction::Str(StrOp::Upper) },
+            "str.lower"  => { arity(0)?; Instruction::Str(StrOp::Lower) },
+            "str.starts" => { arity(0)?; Instruction::Str(StrOp::StartsWith) },
+            "str.ends"   => { arity(0)?; Instruction::Str(StrOp::EndsWith) },
+            "str.eq"     => { arity(0)?; Instruction::Str(StrOp::Eql) },
+            "str.gt"     => { arity(0)?; Instruction::Str(StrOp::GreaterThan) },
+            "str.lt"     => { arity(0)?; Instruction::Str(StrOp::LessThan) },
+            "str.gte"    => { arity(0)?; Instruction::Str(StrOp::GreaterThanEq) },
+            "str.lte"    => { arity(0)?; Instruction::Str(StrOp::LessThanEq) },
+            "str.format" => { arity(0)?; Instruction::Str(StrOp::Format) },
+            "str.parse"  => { arity(1)?; Instruction::Str(StrOp::Parse(self.numeric_type_operand(operands[0])?)) },
+            "and"      => { arity(0)?; Instruction::Logic(LogicOp::And) },
+            "or"       => { arity(0)?; Instruction::Logic(LogicOp::Or) },
+            "xor"      => { arity(0)?; Instruction::Logic(LogicOp::Xor) },
+            "not"      => { arity(0)?; Instruction::Logic(LogicOp::Not) },
+            "equal"    => { arity(0)?; Instruction::Logic(LogicOp::Eq) },
+            "unequal"  => { arity(0)?; Instruction::Logic(LogicOp::Ne) },
+            "arr.new"    => { arity(0)?; Instruction::Array(ArrayOp::New) },
+            "arr.push"   => { arity(0)?; Instruction::Array(ArrayOp::Push) },
+            "arr.pop"    => { arity(0)?; Instruction::Array(ArrayOp::Pop) },
+            "arr.get"    => { arity(0)?; Instruction::Array(ArrayOp::Get) },
+            "arr.set"    => { arity(0)?; Instruction::Array(ArrayOp::Set) },
+            "arr.len"    => { arity(0)?; Instruction::Array(ArrayOp::Len) },
+            "arr.slice"  => { arity(0)?; Instruction::Array(ArrayOp::Slice) },
+            "arr.pack"   => { arity(0)?; Instruction::Array(ArrayOp::Pack) },
+            "arr.unpack" => { arity(0)?; Instruction::Array(ArrayOp::Unpack) },
+            "map.new"    => { arity(0)?; Instruction::Map(MapOp::New) },
+            "map.insert" => { arity(0)?; Instruction::Map(MapOp::Insert) },
+            "map.get"    => { arity(0)?; Instruction::Map(MapOp::Get) },
+            "map.getor"  => { arity(0)?; Instruction::Map(MapOp::GetOr) },
+            "map.remove" => { arity(0)?; Instruction::Map(MapOp::Remove) },
+            "map.has"    => { arity(0)?; Instruction::Map(MapOp::Contains) },
+            "map.len"    => { arity(0)?; Instruction::Map(MapOp::Len) },
+            "map.keys"   => { arity(0)?; Instruction::Map(MapOp::Keys) },
+            "map.values" => { arity(0)?; Instruction::Map(MapOp::Values) },
+            "struct.new" => { arity(1)?; Instruction::Struct(StructOp::New(self.struct_operand(operands[0])?)) },
+            "struct.get" => { arity(1)?; Instruction::Struct(StructOp::Get(self.field_operand(operands[0])?)) },
+            "struct.set" => { arity(1)?; Instruction::Struct(StructOp::Set(self.field_operand(operands[0])?)) },
+            "call"     => { arity(1)?; Instruction::Control(ControlOp::Call(self.value_type(operands[0])?)) },
+            "callif"   => {
+                arity(2)?;
+                Instruction::Control(ControlOp::CallIf(self.value_type(operands[0])?, self.value_type(operands[1])?))
+            },
+            "callelse" => {
+                arity(2)?;
+                Instruction::Control(ControlOp::CallElse(self.value_type(operands[0])?, self.value_type(operands[1])?))
+            },
+            "tailcall" => { arity(1)?; Instruction::Control(ControlOp::TailCall(self.value_type(operands[0])?)) },
+            "ret"      => { arity(0)?; Instruction::Control(ControlOp::Return) },
+            "retif"    => { arity(1)?; Instruction::Control(ControlOp::ReturnIf(self.value_type(operands[0])?)) },
+            "jmp"      => { arity(1)?; Instruction::Control(ControlOp::Jump(self.jump_target_operand(operands[0])?)) },
+            "jmpif"    => {
+                arity(2)?;
+                Instruction::Control(ControlOp::JumpIf(self.jump_target_operand(operands[0])?, self.value_type(operands[1])?))
+            },
+            "jmpelse"  => {
+                arity(2)?;
+                Instruction::Control(ControlOp::JumpElse(self.jump_target_operand(operands[0])?, self.value_type(operands[1])?))
+            },
+            _ => return Err(AsmError::new(position, &format!("Unknown instruction '{}'", mnemonic)))
+        };
+
+        cursor.finish()?;
+
+        Ok(instruction)
+    }
+
+    fn function_header(&self, cursor: &mut Cursor) -> Result<PendingFunction, AsmError> {
+        let (name, position) = cursor.expect_ident("function name")?;
+
+        let mut function = Function {
+            ptr_recipie: vec![],
+            instructions: vec![],
+            param_count: 0,
+            return_count: 0
+        };
+
+        while cursor.peek().is_some() {
+            let (attribute, attribute_position) = cursor.expect_ident("function attribute")?;
+            cursor.expect(TokenKind::Equals)?;
+
+            match attribute {
+                "id" => { cursor.next(); },
+                "params" => function.param_count = self.usize_attribute(cursor.expect_next("param count")?)?,
+                "returns" => function.return_count = self.usize_attribute(cursor.expect_next("return count")?)?,
+                "recipe" => function.ptr_recipie = self.value_list(cursor)?,
+                _ => return Err(AsmError::new(attribute_position, &format!("Unknown function attribute '{}'", attribute)))
+            }
+        }
+
+        Ok(PendingFunction { name: name.to_string(), position, function })
+    }
+
+    // `struct Name field: type, ...`, the fields in the order `struct.new` takes them.
+    fn struct_definition(&self, cursor: &mut Cursor) -> Result<StructDef, AsmError> {
+        let (name, _) = cursor.expect_ident("struct name")?;
+        let mut fields: Vec<(String, DataType)> = vec![];
+
+        while cursor.peek().is_some() {
+            if !fields.is_empty() {
+                cursor.expect(TokenKind::Comma)?;
+            }
+
+            let (field, position) = cursor.expect_ident("field name")?;
+            cursor.expect(TokenKind::Colon)?;
+            let data_type = self.data_type_operand(cursor.expect_next("field type")?)?;
+
+            if fields.iter().any(|(existing, _)| existing == field) {
+                return Err(AsmError::new(position, &format!("Field '{}' is already defined", field)));
+            }
+
+            fields.push((field.to_string(), data_type));
+        }
+
+        Ok(StructDef { name: name.to_string(), fields })
+    }
+
+    // Finds the jump labels of the function body starting at `lines`, up to its `end`.
+    fn jump_labels(lines: &[Line]) -> Result<HashMap<String, usize>, AsmError> {
+        let mut labels = HashMap::new();
+        let mut index = 0;
+
+        for line in lines {
+            // Checked first so labels such as `end:` don't end the function.
+            if let Some(name) = line.jump_label() {
+                if labels.insert(name.to_string(), index).is_some() {
+                    return Err(AsmError::new(line_start(line), &format!("Jump label '{}' is already defined", name)));
+                }
+
+                continue;
+            }
+
+            match line.tokens.first().map(|token| &token.kind) {
+                Some(TokenKind::Ident(keyword)) if keyword == "end" || keyword == "fn" => break,
+                None => continue,
+                _ => index += 1
+            }
+        }
+
+        Ok(labels)
+    }
+
+    // First pass: collect function labels, ptr names and structs so operands may refer forward.
+    fn declare(&mut self, lines: &[Line]) -> Result<(), AsmError> {
+        let mut declared: Vec<(String, Option<usize>, Position)> = vec![];
+        let mut declared_ptrs = HashSet::new();
+        let mut declared_structs = HashSet::new();
+
+        for line in lines {
+            let mut cursor = Cursor::new(line);
+
+            if line.jump_label().is_some() {
+                continue;
+            }
+
+            match cursor.next().map(|token| &token.kind) {
+                Some(TokenKind::Ident(keyword)) if keyword == "fn" => {
+                    let (name, position) = cursor.expect_ident("function name")?;
+                    let mut id = None;
+
+                    while let Some(token) = cursor.next() {
+                        if token.kind == TokenKind::Ident("id".to_string()) {
+                            cursor.expect(TokenKind::Equals)?;
+                            id = Some(self.usize_attribute(cursor.expect_next("function id")?)?);
+                        }
+                    }
+
+                    if ["stack", "true", "false"].contains(&name) {
+                        return Err(AsmError::new(position, &format!("'{}' is reserved and cannot name a function", name)));
+                    }
+
+                    if declared.iter().any(|(declared_name, _, _)| declared_name == name) {
+                        return Err(AsmError::new(position, &format!("Function '{}' is already defined", name)));
+                    }
+
+                    declared.push((name.to_string(), id, position));
+                },
+                Some(TokenKind::Ident(keyword)) if keyword == "ptr" || keyword == "export" => {
+                    if keyword == "export" {
+                        let (keyword, position) = cursor.expect_ident("'ptr'")?;
+
+                        if keyword != "ptr" {
+                            return Err(AsmError::new(position, &format!("Expected 'ptr' after 'export', found '{}'", keyword)));
+                        }
+                    }
+
+                    let (name, position) = cursor.expect_ident("ptr name")?;
+
+                    if !declared_ptrs.insert(name) {
+                        return Err(AsmError::new(position, &format!("Ptr '{}' is already defined", name)));
+                    }
+
+                    if !self.ptrs.iter().any(|(ptr_name, _)| ptr_name == name) {
+                        self.ptrs.push((name.to_string(), Ptr::new(Value::Bool(false))));
+                    }
+
+                    if keyword == "export" {
+                        self.exports.insert(name.to_string());
+                    }
+                },
+                Some(TokenKind::Ident(keyword)) if keyword == "struct" => {
+                    let position = cursor.peek().map_or_else(|| cursor.end_position(), |token| token.position);
+                    let definition = self.struct_definition(&mut cursor)?;
+
+                    if !declared_structs.insert(definition.name.clone()) {
+                        return Err(AsmError::new(position, &format!("Struct '{}' is already defined", definition.name)));
+                    }
+
+                    self.structs.insert(definition.name.clone(), Rc::new(definition));
+                },
+                _ => {}
+            }
+        }
+
+        let mut used_ids = self.reserved_ids.clone();
+
+        for (name, id, position) in &declared {
+            if let Some(id) = id {
+                let redefined = self.labels.get(name) == Some(id);
+
+                if !redefined && !used_ids.insert(*id) {
+                    return Err(AsmError::new(*position, &format!("Function id {} is already in use", id)));
+                }
+
+                self.labels.insert(name.clone(), *id);
+            }
+        }
+
+        let mut next_id = 1;
+
+        for (name, id, _) in &declared {
+            if id.is_none() && !self.labels.contains_key(name) {
+                while used_ids.contains(&next_id) {
+                    next_id += 1;
+                }
+
+                used_ids.insert(next_id);
+                self.labels.insert(name.clone(), next_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn assemble(mut self, lines: &[Line]) -> Result<Program, AsmError> {
+        self.declare(lines)?;
+
+        let mut functions = HashMap::new();
+        let mut current: Option<PendingFunction> = None;
+
+        for (line_index, line) in lines.iter().enumerate() {
+            let mut cursor = Cursor::new(line);
+
+            if let (Some(_), Some(_)) = (line.jump_label(), &current) {
+                continue;
+            }
+
+            let keyword = match cursor.peek().map(|token| &token.kind) {
+                Some(TokenKind::Ident(keyword)) => keyword.as_str(),
+                Some(_) => {
+                    let token = cursor.peek().unwrap();
+                    return Err(AsmError::new(token.position, &format!("Expected instruction, found {}", describe(&token.kind))));
+                },
+                None => continue
+            };
+
+            match (keyword, &mut current) {
+                ("fn", Some(pending)) => {
+                    return Err(AsmError::new(cursor.peek().unwrap().position, &format!("Missing 'end' for function '{}'", pending.name)));
+                },
+                ("fn", None) => {
+                    cursor.next();
+                    current = Some(self.function_header(&mut cursor)?);
+                    self.jump_labels = Assembler::jump_labels(&lines[line_index + 1..])?;
+                },
+                ("end", Some(_)) => {
+                    cursor.next();
+                    cursor.finish()?;
+
+                    let pending = current.take().unwrap();
+                    functions.insert(self.labels[&pending.name], pending.function);
+                },
+                // Structs were read by `declare`.
+                ("struct", None) => continue,
+                ("ptr", None) | ("export", None) => {
+                    if keyword == "export" {
+                        cursor.next();
+                    }
+
+                    cursor.next();
+                    let (name, _) = cursor.expect_ident("ptr name")?;
+                    cursor.expect(TokenKind::Equals)?;
+                    let value = self.value(cursor.expect_next("ptr value")?)?;
+                    cursor.finish()?;
+
+                    self.ptr(name, line_start(line))?.value.replace(value);
+                },
+                (_, Some(pending)) => {
+                    let instruction = self.instruction(&mut cursor)?;
+                    pending.function.instructions.push(instruction);
+                },
+                (keyword, None) => {
+                    return Err(AsmError::new(cursor.peek().unwrap().position, &format!("Unexpected '{}' outside of a function", keyword)));
+                }
+            }
+        }
+
+        if let Some(pending) = current {
+            return Err(AsmError::new(pending.position, &format!("Missing 'end' for function '{}'", pending.name)));
+        }
+
+        Ok(Program {
+            functions,
+            structs: self.structs,
+            labels: self.labels,
+            ptrs: self.ptrs,
+            exports: self.exports
+        })
+    }
+}
+
+
+fn line_start(line: &Line) -> Position {
+    line.tokens.first().map_or(Position { line: line.number, column: 1 }, |token| token.position)
+}
+
+
+fn lex(source: &str) -> Result<Vec<Line>, AsmError> {
+    source.lines()
+        .enumerate()
+        .map(|(index, line)| {
+            Ok(Line {
+                number: index + 1,
+                length: line.chars().count(),
+                tokens: lex_line(line, index + 1)?
+            })
+        })
+        .collect()
+}
+
+
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    Assembler::new().assemble(&lex(source)?)
+}
+
+
+// Assembles source that may use the labels and ptrs of `base`. The result holds only the
+// newly defined functions, along with the labels and ptrs of both.
+pub fn assemble_into(source: &str, base: &Program) -> Result<Program, AsmError> {
+    Assembler::from_program(base).assemble(&lex(source)?)
+}
+
+
+pub fn assemble_instruction(source: &str, base: &Program) -> Result<Instruction, AsmError> {
+    let lines = lex(source)?;
+
+    match lines.as_slice() {
+        [line] if !line.tokens.is_empty() => Assembler::from_program(base).instruction(&mut Cursor::new(line)),
+        _ => Err(AsmError::new(Position { line: 1, column: 1 }, "Expected a single instruction"))
+    }
+}
+
+
+pub fn parse_value(text: &str) -> Result<Value, AsmError> {
+    let tokens = lex_line(text, 1)?;
+
+    match tokens.as_slice() {
+        [token] => Assembler::new().value(token),
+        [] => Err(AsmError::new(Position { line: 1, column: 1 }, "Expected value, found nothing")),
+        [_, token, ..] => Err(AsmError::new(token.position, &format!("Unexpected {}", describe(&token.kind))))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instructions(program: &Program, name: &str) -> Vec<Instruction> {
+        program.functions[&program.function_id(name).unwrap()].instructions.clone()
+    }
+
+    #[test]
+    fn keywords_can_name_jump_labels() {
+        let program = assemble("fn main params=0 returns=0\n    jmpif end, stack\n    push 1i32\nend:\n    push 2i32\nfn:\nend\n").unwrap();
+
+        let targets: Vec<String> = instructions(&program, "main").iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Control(ControlOp::JumpIf(target, _)) => Some(target.to_string()),
+                _ => None
+            })
+            .collect();
+
+        assert_eq!(targets, ["2"]);
+        assert_eq!(instructions(&program, "main").len(), 3);
+    }
+
+    fn error(source: &str) -> String {
+        match assemble(source) {
+            Ok(_) => panic!("the source should not assemble"),
+            Err(error) => error.to_string()
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(error("fn main params=0 returns=0\n    push 1i32\n    frob\nend\n"), "3:5: Unknown instruction 'frob'");
+        assert_eq!(error("fn main params=0 returns=0\n    push @missing\nend\n"), "2:10: Unknown ptr 'missing'");
+        assert_eq!(error("fn main params=0 returns=0\n    push 1q32\nend\n"), "2:10: Invalid numeric literal '1q32'");
+        assert_eq!(error("fn main params=0 returns=0\n    push 1i32\n"), "1:4: Missing 'end' for function 'main'");
+    }
+
+    #[test]
+    fn literals_keep_their_types() {
+        let literals = [
+            ("42", "42i32"),
+            ("3.5", "3.5f64"),
+            ("255u8", "255u8"),
+            ("-7i8", "-7i8"),
+            ("2.5f32", "2.5f32"),
+            ("170141183460469231731687303715884105727i128", "170141183460469231731687303715884105727i128"),
+            ("9usize", "9usize"),
+            ("\"a b\"", "\"a b\""),
+            ("true", "true"),
+            ("#u16", "#u16"),
+        ];
+
+        for (literal, value) in literals {
+            assert_eq!(parse_value(literal).unwrap().to_string(), value, "{}", literal);
+        }
+
+        assert!(parse_value("256u8").is_err());
+    }
+
+    #[test]
+    fn functions_can_be_called_before_they_are_declared() {
+        let program = assemble("fn main params=0 returns=0\n    call later\nend\n\nfn later params=0 returns=0\nend\n").unwrap();
+        let later = program.function_id("later").unwrap();
+
+        assert_eq!(later, 2);
+        assert!(matches!(instructions(&program, "main")[..], [Instruction::Control(ControlOp::Call(ValueType::Value(Value::Numeric(Numeric::USize(id)))))] if id == later));
+    }
+}
@@ -0,0 +1,1354 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::numeric::{Numeric, NumericType};
+use crate::value::{Value, ValueType, MapKey, sorted_entries};
+use crate::data_type::{DataType, Typed};
+use crate::ptr::Ptr;
+use crate::instruction::Instruction;
+use crate::stack_op::StackOp;
+use crate::math_op::MathOp;
+use crate::type_op::TypeOp;
+use crate::str_op::StrOp;
+use crate::logic_op::LogicOp;
+use crate::array_op::ArrayOp;
+use crate::map_op::MapOp;
+use crate::struct_op::StructOp;
+use crate::struct_def::{StructDef, Struct, Field};
+use crate::control_op::ControlOp;
+use crate::control::JumpTarget;
+use crate::function::Function;
+use crate::program::{Program, collect_ptrs};
+
+
+// Layout: magic, version (u16), payload length (u32), crc32 of payload (u32), payload.
+// All fixed width integers are little endian, counts and indexes are LEB128 varints.
+pub const MAGIC: &[u8; 4] = b"VMBC";
+pub const VERSION: u16 = 3;
+
+const HEADER_LEN: usize = 14;
+
+const FAMILY_STACK: u8 = 0;
+const FAMILY_MATH: u8 = 1;
+const FAMILY_TYPE: u8 = 2;
+const FAMILY_CONTROL: u8 = 3;
+const FAMILY_STR: u8 = 4;
+const FAMILY_LOGIC: u8 = 5;
+const FAMILY_ARRAY: u8 = 6;
+const FAMILY_MAP: u8 = 7;
+const FAMILY_STRUCT: u8 = 8;
+
+const VALUE_TYPE_STACK: u8 = 0;
+const VALUE_TYPE_PTR: u8 = 1;
+const VALUE_TYPE_VALUE: u8 = 2;
+const VALUE_TYPE_LOCAL: u8 = 3;
+
+const VALUE_STR: u8 = 0;
+const VALUE_NUMERIC: u8 = 1;
+const VALUE_BOOL: u8 = 2;
+const VALUE_PTR: u8 = 3;
+const VALUE_TYPE: u8 = 4;
+// Followed by the item count and every item.
+const VALUE_ARRAY: u8 = 5;
+// Followed by the entry count and every key and value, keys in order.
+const VALUE_MAP: u8 = 6;
+// Followed by the struct name and every field, in declaration order.
+const VALUE_STRUCT: u8 = 7;
+
+// Limits how deeply arrays, maps and structs may nest so decoding can't run out of stack. The
+// encoder enforces it too, so everything it writes can be read back.
+const MAX_VALUE_DEPTH: usize = 64;
+
+// A numeric data type is followed by its numeric type tag.
+const DATA_TYPE_STR: u8 = 0;
+const DATA_TYPE_NUMERIC: u8 = 1;
+const DATA_TYPE_BOOL: u8 = 2;
+const DATA_TYPE_PTR: u8 = 3;
+const DATA_TYPE_TYPE: u8 = 4;
+const DATA_TYPE_ARRAY: u8 = 5;
+const DATA_TYPE_MAP: u8 = 6;
+const DATA_TYPE_STRUCT: u8 = 7;
+
+// A field name is followed by the name, a field index by the index.
+const FIELD_NAME: u8 = 0;
+const FIELD_INDEX: u8 = 1;
+
+// Relative offsets are zigzag encoded before being written as a varint.
+const JUMP_ABSOLUTE: u8 = 0;
+const JUMP_RELATIVE: u8 = 1;
+
+
+#[derive(Debug)]
+pub struct DecodeError {
+    pub offset: usize,
+    pub message: String
+}
+
+
+impl DecodeError {
+    pub fn new(offset: usize, message: &str) -> DecodeError {
+        DecodeError {
+            offset,
+            message: message.to_string()
+        }
+    }
+}
+
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "byte {}: {}", self.offset, self.message)
+    }
+}
+
+
+impl std::error::Error for DecodeError {}
+
+
+// Only values built at runtime can fail to encode, anything assembled or decoded always fits.
+#[derive(Debug)]
+pub struct EncodeError {
+    pub message: String
+}
+
+
+impl EncodeError {
+    pub fn new(message: &str) -> EncodeError {
+        EncodeError {
+            message: message.to_string()
+        }
+    }
+}
+
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+
+impl std::error::Error for EncodeError {}
+
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+
+fn numeric_type_tag(numeric_type: &NumericType) -> u8 {
+    match numeric_type {
+        NumericType::UInt8   => 0,
+        NumericType::UInt16  => 1,
+        NumericType::UInt32  => 2,
+        NumericType::UInt64  => 3,
+        NumericType::UInt128 => 4,
+        NumericType::Int8    => 5,
+        NumericType::Int16   => 6,
+        NumericType::Int32   => 7,
+        NumericType::Int64   => 8,
+        NumericType::Int128  => 9,
+        NumericType::Float32 => 10,
+        NumericType::Float64 => 11,
+        NumericType::USize   => 12,
+        NumericType::ISize   => 13,
+    }
+}
+
+
+struct Encoder {
+    bytes: Vec<u8>,
+    ptr_indexes: HashMap<usize, usize>,
+    depth: usize
+}
+
+
+impl Encoder {
+    fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+
+            if value == 0 {
+                self.byte(byte);
+                return;
+            }
+
+            self.byte(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, string: &str) {
+        self.varint(string.len() as u64);
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    fn ptr(&mut self, ptr: &Ptr) {
+        let index = self.ptr_indexes[&ptr.address()];
+        self.varint(index as u64);
+    }
+
+    fn numeric_type(&mut self, numeric_type: &NumericType) {
+        self.byte(numeric_type_tag(numeric_type));
+    }
+
+    fn numeric(&mut self, numeric: &Numeric) {
+        self.numeric_type(&numeric.numeric_type());
+
+        match numeric {
+            Numeric::UInt8(a)   => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::UInt16(a)  => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::UInt32(a)  => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::UInt64(a)  => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::UInt128(a) => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::Int8(a)    => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::Int16(a)   => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::Int32(a)   => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::Int64(a)   => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::Int128(a)  => self.bytes.extend_from_slice(&a.to_le_bytes()),
+            Numeric::Float32(a) => self.bytes.extend_from_slice(&a.to_bits().to_le_bytes()),
+            Numeric::Float64(a) => self.bytes.extend_from_slice(&a.to_bits().to_le_bytes()),
+            Numeric::USize(a)   => self.bytes.extend_from_slice(&(*a as u64).to_le_bytes()),
+            Numeric::ISize(a)   => self.bytes.extend_from_slice(&(*a as i64).to_le_bytes()),
+        }
+    }
+
+    fn data_type(&mut self, data_type: &DataType) {
+        match data_type {
+            DataType::Str => self.byte(DATA_TYPE_STR),
+            DataType::Numeric(numeric_type) => {
+                self.byte(DATA_TYPE_NUMERIC);
+                self.numeric_type(numeric_type);
+            },
+            DataType::Bool => self.byte(DATA_TYPE_BOOL),
+            DataType::Ptr => self.byte(DATA_TYPE_PTR),
+            DataType::Type => self.byte(DATA_TYPE_TYPE),
+            DataType::Array => self.byte(DATA_TYPE_ARRAY),
+            DataType::Map => self.byte(DATA_TYPE_MAP),
+            DataType::Struct => self.byte(DATA_TYPE_STRUCT)
+        }
+    }
+
+    fn value(&mut self, value: &Value) -> Result<(), EncodeError> {
+        match value {
+            Value::Str(string) => {
+                self.byte(VALUE_STR);
+                self.string(string);
+            },
+            Value::Numeric(numeric) => {
+                self.byte(VALUE_NUMERIC);
+                self.numeric(numeric);
+            },
+            Value::Bool(boolean) => {
+                self.byte(VALUE_BOOL);
+                self.byte(*boolean as u8);
+            },
+            Value::Ptr(ptr) => {
+                self.byte(VALUE_PTR);
+                self.ptr(ptr);
+            },
+            Value::Type(data_type) => {
+                self.byte(VALUE_TYPE);
+                self.data_type(data_type);
+            },
+            Value::Array(_) | Value::Map(_) | Value::Struct(_) => {
+                if self.depth == MAX_VALUE_DEPTH {
+                    return Err(EncodeError::new("Arrays, maps and structs are nested too deeply"));
+                }
+
+                self.depth += 1;
+                self.nested_value(value)?;
+                self.depth -= 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn nested_value(&mut self, value: &Value) -> Result<(), EncodeError> {
+        match value {
+            Value::Array(items) => {
+                self.byte(VALUE_ARRAY);
+                self.varint(items.len() as u64);
+
+                for item in items.iter() {
+                    self.value(item)?;
+                }
+            },
+            Value::Map(map) => {
+                self.byte(VALUE_MAP);
+                self.varint(map.len() as u64);
+
+                for (key, value) in sorted_entries(map) {
+                    self.value(&key.to_value())?;
+                    self.value(value)?;
+                }
+            },
+            Value::Struct(instance) => {
+                self.byte(VALUE_STRUCT);
+                self.string(&instance.definition.name);
+
+                for field in &instance.fields {
+                    self.value(field)?;
+                }
+            },
+            _ => unreachable!()
+        }
+
+        Ok(())
+    }
+
+    fn field(&mut self, field: &Field) {
+        match field {
+            Field::Name(name) => {
+                self.byte(FIELD_NAME);
+                self.string(name);
+            },
+            Field::Index(index) => {
+                self.byte(FIELD_INDEX);
+                self.varint(*index as u64);
+            }
+        }
+    }
+
+    fn value_type(&mut self, value: &ValueType) -> Result<(), EncodeError> {
+        match value {
+            ValueType::StackValue => self.byte(VALUE_TYPE_STACK),
+            ValueType::Ptr(ptr) => {
+                self.byte(VALUE_TYPE_PTR);
+                self.ptr(ptr);
+            },
+            ValueType::Value(value) => {
+                self.byte(VALUE_TYPE_VALUE);
+                self.value(value)?;
+            },
+            ValueType::Local(index) => {
+                self.byte(VALUE_TYPE_LOCAL);
+                self.varint(*index as u64);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn jump_target(&mut self, target: &JumpTarget) {
+        match target {
+            JumpTarget::Absolute(index) => {
+                self.byte(JUMP_ABSOLUTE);
+                self.varint(*index as u64);
+            },
+            JumpTarget::Relative(offset) => {
+                let offset = *offset as i64;
+
+                self.byte(JUMP_RELATIVE);
+                self.varint(((offset << 1) ^ (offset >> 63)) as u64);
+            }
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) -> Result<(), EncodeError> {
+        match instruction {
+            Instruction::Stack(op) => {
+                self.byte(FAMILY_STACK);
+
+                match op {
+                    StackOp::Swap            => self.byte(0),
+                    StackOp::Duplicate       => self.byte(1),
+                    StackOp::Drop            => self.byte(2),
+                    StackOp::Pop(ptr)        => { self.byte(3); self.ptr(ptr); },
+                    StackOp::Push(value)     => { self.byte(4); self.value_type(value)?; },
+                    StackOp::PushPtr(ptr)    => { self.byte(5); self.ptr(ptr); },
+                    StackOp::DeRef           => self.byte(6),
+                    StackOp::SubStack(value) => { self.byte(7); self.value_type(value)?; },
+                    StackOp::Destack(value)  => { self.byte(8); self.value_type(value)?; },
+                    StackOp::Len             => self.byte(9),
+                    StackOp::Inspect         => self.byte(10),
+                    StackOp::Alloc           => self.byte(11),
+                    StackOp::Store           => self.byte(12),
+                    StackOp::StoreLocal(index) => { self.byte(13); self.varint(*index as u64); },
+                }
+            },
+            Instruction::Math(op) => {
+                self.byte(FAMILY_MATH);
+
+                match op {
+                    MathOp::Add           => self.byte(0),
+                    MathOp::Sub           => self.byte(1),
+                    MathOp::Mul           => self.byte(2),
+                    MathOp::Div           => self.byte(3),
+                    MathOp::GreaterThan   => self.byte(4),
+                    MathOp::LessThan      => self.byte(5),
+                    MathOp::GreaterThanEq => self.byte(6),
+                    MathOp::LessThanEq    => self.byte(7),
+                    MathOp::Eql           => self.byte(8),
+                    MathOp::Rem           => self.byte(9),
+                    MathOp::Neg           => self.byte(10),
+                    MathOp::Abs           => self.byte(11),
+                    MathOp::BitAnd        => self.byte(12),
+                    MathOp::BitOr         => self.byte(13),
+                    MathOp::BitXor        => self.byte(14),
+                    MathOp::Not           => self.byte(15),
+                    MathOp::Shl           => self.byte(16),
+                    MathOp::Shr           => self.byte(17),
+                    MathOp::RotateLeft    => self.byte(18),
+                    MathOp::RotateRight   => self.byte(19),
+                    MathOp::Min           => self.byte(20),
+                    MathOp::Max           => self.byte(21),
+                    MathOp::Pow           => self.byte(22),
+                    MathOp::WrappingAdd   => self.byte(23),
+                    MathOp::WrappingSub   => self.byte(24),
+                    MathOp::WrappingMul   => self.byte(25),
+                    MathOp::WrappingDiv   => self.byte(26),
+                    MathOp::WrappingRem   => self.byte(27),
+                    MathOp::WrappingNeg   => self.byte(28),
+                    MathOp::WrappingAbs   => self.byte(29),
+                    MathOp::WrappingPow   => self.byte(30),
+                    MathOp::SaturatingAdd => self.byte(31),
+                    MathOp::SaturatingSub => self.byte(32),
+                    MathOp::SaturatingMul => self.byte(33),
+                    MathOp::SaturatingDiv => self.byte(34),
+                    MathOp::SaturatingRem => self.byte(35),
+                    MathOp::SaturatingNeg => self.byte(36),
+                    MathOp::SaturatingAbs => self.byte(37),
+                    MathOp::SaturatingPow => self.byte(38),
+                }
+            },
+            Instruction::Type(op) => {
+                self.byte(FAMILY_TYPE);
+
+                match op {
+                    TypeOp::NumericCast(to) => { self.byte(0); self.numeric_type(to); },
+                    TypeOp::TypeOf => self.byte(1),
+                    TypeOp::Is(data_type) => { self.byte(2); self.data_type(data_type); },
+                    TypeOp::CheckedCast(to) => { self.byte(3); self.numeric_type(to); },
+                    TypeOp::SaturatingCast(to) => { self.byte(4); self.numeric_type(to); },
+                }
+            },
+            Instruction::Str(op) => {
+                self.byte(FAMILY_STR);
+
+                match op {
+                    StrOp::Concat        => self.byte(0),
+                    StrOp::Len           => self.byte(1),
+                    StrOp::CharLen       => self.byte(2),
+                    StrOp::Substring     => self.byte(3),
+                    StrOp::IndexOf       => self.byte(4),
+                    StrOp::Split         => self.byte(5),
+                    StrOp::Join          => self.byte(6),
+                    StrOp::Trim          => self.byte(7),
+                    StrOp::Upper         => self.byte(8),
+                    StrOp::Lower         => self.byte(9),
+                    StrOp::StartsWith    => self.byte(10),
+                    StrOp::EndsWith      => self.byte(11),
+                    StrOp::Eql           => self.byte(12),
+                    StrOp::GreaterThan   => self.byte(13),
+                    StrOp::LessThan      => self.byte(14),
+                    StrOp::GreaterThanEq => self.byte(15),
+                    StrOp::LessThanEq    => self.byte(16),
+                    StrOp::Format        => self.byte(17),
+                    StrOp::Parse(to)     => { self.byte(18); self.numeric_type(to); },
+                }
+            },
+            Instruction::Logic(op) => {
+                self.byte(FAMILY_LOGIC);
+
+                match op {
+                    LogicOp::And => self.byte(0),
+                    LogicOp::Or  => self.byte(1),
+                    LogicOp::Xor => self.byte(2),
+                    LogicOp::Not => self.byte(3),
+                    LogicOp::Eq  => self.byte(4),
+                    LogicOp::Ne  => self.byte(5),
+                }
+            },
+            Instruction::Array(op) => {
+                self.byte(FAMILY_ARRAY);
+
+                match op {
+                    ArrayOp::New    => self.byte(0),
+                    ArrayOp::Push   => self.byte(1),
+                    ArrayOp::Pop    => self.byte(2),
+                    ArrayOp::Get    => self.byte(3),
+                    ArrayOp::Set    => self.byte(4),
+                    ArrayOp::Len    => self.byte(5),
+                    ArrayOp::Slice  => self.byte(6),
+                    ArrayOp::Pack   => self.byte(7),
+                    ArrayOp::Unpack => self.byte(8),
+                }
+            },
+            Instruction::Map(op) => {
+                self.byte(FAMILY_MAP);
+
+                match op {
+                    MapOp::New      => self.byte(0),
+                    MapOp::Insert   => self.byte(1),
+                    MapOp::Get      => self.byte(2),
+                    MapOp::GetOr    => self.byte(3),
+                    MapOp::Remove   => self.byte(4),
+                    MapOp::Contains => self.byte(5),
+                    MapOp::Len      => self.byte(6),
+                    MapOp::Keys     => self.byte(7),
+                    MapOp::Values   => self.byte(8),
+                }
+            },
+            Instruction::Struct(op) => {
+                self.byte(FAMILY_STRUCT);
+
+                match op {
+                    StructOp::New(name)  => { self.byte(0); self.string(name); },
+                    StructOp::Get(field) => { self.byte(1); self.field(field); },
+                    StructOp::Set(field) => { self.byte(2); self.field(field); },
+                }
+            },
+            Instruction::Control(op) => {
+                self.byte(FAMILY_CONTROL);
+
+                match op {
+                    ControlOp::Call(function) => { self.byte(0); self.value_type(function)?; },
+                    ControlOp::CallIf(function, predicate) => {
+                        self.byte(1);
+                        self.value_type(function)?;
+                        self.value_type(predicate)?;
+                    },
+                    ControlOp::CallElse(function, predicate) => {
+                        self.byte(2);
+                        self.value_type(function)?;
+                        self.value_type(predicate)?;
+                    },
+                    ControlOp::Return => self.byte(3),
+                    ControlOp::ReturnIf(predicate) => { self.byte(4); self.value_type(predicate)?; },
+                    ControlOp::Jump(target) => { self.byte(5); self.jump_target(target); },
+                    ControlOp::JumpIf(target, predicate) => {
+                        self.byte(6);
+                        self.jump_target(target);
+                        self.value_type(predicate)?;
+                    },
+                    ControlOp::JumpElse(target, predicate) => {
+                        self.byte(7);
+                        self.jump_target(target);
+                        self.value_type(predicate)?;
+                    },
+                    ControlOp::TailCall(function) => { self.byte(8); self.value_type(function)?; },
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn struct_definition(&mut self, definition: &StructDef) {
+        self.string(&definition.name);
+        self.varint(definition.fields.len() as u64);
+
+        for (name, data_type) in &definition.fields {
+            self.string(name);
+            self.data_type(data_type);
+        }
+    }
+
+    fn function(&mut self, id: usize, function: &Function) -> Result<(), EncodeError> {
+        self.varint(id as u64);
+        self.varint(function.param_count as u64);
+        self.varint(function.return_count as u64);
+
+        self.varint(function.ptr_recipie.len() as u64);
+        for value in &function.ptr_recipie {
+            self.value(value)?;
+        }
+
+        self.varint(function.instructions.len() as u64);
+        for instruction in &function.instructions {
+            self.instruction(instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+pub fn encode(program: &Program) -> Result<Vec<u8>, EncodeError> {
+    let ptrs = collect_ptrs(&program.functions, &program.ptrs);
+
+    let mut encoder = Encoder {
+        bytes: vec![],
+        ptr_indexes: ptrs.iter().enumerate().map(|(index, ptr)| (ptr.address(), index)).collect(),
+        depth: 0
+    };
+
+    let mut names: Vec<&String> = program.structs.keys().collect();
+    names.sort();
+
+    encoder.varint(names.len() as u64);
+    for name in names {
+        encoder.struct_definition(&program.structs[name]);
+    }
+
+    encoder.varint(ptrs.len() as u64);
+    for (index, ptr) in ptrs.iter().enumerate() {
+        let name = program.ptrs.iter()
+            .find(|(_, named)| named.address() == ptr.address())
+            .map_or("", |(name, _)| name.as_str());
+
+        encoder.string(name);
+        encoder.byte(program.exports.contains(name) as u8);
+        encoder.value(&ptr.value.borrow()).map_err(|error| {
+            let ptr = if name.is_empty() { format!("#{}", index) } else { name.to_string() };
+            EncodeError::new(&format!("Ptr {}: {}", ptr, error))
+        })?;
+    }
+
+    let mut labels: Vec<(&String, &usize)> = program.labels.iter().collect();
+    labels.sort();
+
+    encoder.varint(labels.len() as u64);
+    for (name, id) in labels {
+        encoder.string(name);
+        encoder.varint(*id as u64);
+    }
+
+    let mut ids: Vec<&usize> = program.functions.keys().collect();
+    ids.sort();
+
+    encoder.varint(ids.len() as u64);
+    for id in ids {
+        encoder.function(*id, &program.functions[id])
+            .map_err(|error| EncodeError::new(&format!("Function {}: {}", id, error)))?;
+    }
+
+    let payload = encoder.bytes;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+
+    Ok(bytes)
+}
+
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    ptrs: Vec<Ptr>,
+    structs: HashMap<String, Rc<StructDef>>,
+    depth: usize
+}
+
+
+impl<'a> Decoder<'a> {
+    fn error(&self, message: &str) -> DecodeError {
+        DecodeError::new(self.offset, message)
+    }
+
+    fn take(&mut self, count: usize, what: &str) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() - self.offset < count {
+            return Err(self.error(&format!("Unexpected end of data reading {}", what)));
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + count];
+        self.offset += count;
+
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self, what: &str) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N, what)?.try_into().unwrap())
+    }
+
+    fn byte(&mut self, what: &str) -> Result<u8, DecodeError> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    fn varint(&mut self, what: &str) -> Result<u64, DecodeError> {
+        let start = self.offset;
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte(what)?;
+            let bits = (byte & 0x7F) as u64;
+
+            if shift == 63 && bits > 1 {
+                break;
+            }
+
+            value |= bits << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::new(start, &format!("Varint for {} overflows 64 bits", what)))
+    }
+
+    fn usize(&mut self, what: &str) -> Result<usize, DecodeError> {
+        let start = self.offset;
+
+        usize::try_from(self.varint(what)?)
+            .map_err(|_| DecodeError::new(start, &format!("Value of {} does not fit in usize", what)))
+    }
+
+    // Counts are checked against the remaining input so a corrupt count can't trigger a huge allocation.
+    fn count(&mut self, what: &str) -> Result<usize, DecodeError> {
+        let start = self.offset;
+        let count = self.usize(what)?;
+
+        if count > self.bytes.len() - self.offset {
+            return Err(DecodeError::new(start, &format!("Count of {} ({}) exceeds remaining data", what, count)));
+        }
+
+        Ok(count)
+    }
+
+    fn string(&mut self, what: &str) -> Result<String, DecodeError> {
+        let length = self.count(what)?;
+        let start = self.offset;
+        let bytes = self.take(length, what)?;
+
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| DecodeError::new(start, &format!("Invalid UTF-8 in {}", what)))
+    }
+
+    fn ptr(&mut self) -> Result<Ptr, DecodeError> {
+        let start = self.offset;
+        let index = self.usize("ptr index")?;
+
+        self.ptrs.get(index)
+            .cloned()
+            .ok_or_else(|| DecodeError::new(start, &format!("Ptr index {} out of range", index)))
+    }
+
+    fn numeric_type(&mut self) -> Result<NumericType, DecodeError> {
+        let tag = self.byte("numeric type")?;
+
+        Ok(match tag {
+            0  => NumericType::UInt8,
+            1  => NumericType::UInt16,
+            2  => NumericType::UInt32,
+            3  => NumericType::UInt64,
+            4  => NumericType::UInt128,
+            5  => NumericType::Int8,
+            6  => NumericType::Int16,
+            7  => NumericType::Int32,
+            8  => NumericType::Int64,
+            9  => NumericType::Int128,
+            10 => NumericType::Float32,
+            11 => NumericType::Float64,
+            12 => NumericType::USize,
+            13 => NumericType::ISize,
+            _  => return Err(DecodeError::new(self.offset - 1, &format!("Unknown numeric type tag {}", tag)))
+        })
+    }
+
+    fn numeric(&mut self) -> Result<Numeric, DecodeError> {
+        let numeric_type = self.numeric_type()?;
+        let start = self.offset;
+
+        Ok(match numeric_type {
+            NumericType::UInt8   => Numeric::UInt8(u8::from_le_bytes(self.array("u8")?)),
+            NumericType::UInt16  => Numeric::UInt16(u16::from_le_bytes(self.array("u16")?)),
+            NumericType::UInt32  => Numeric::UInt32(u32::from_le_bytes(self.array("u32")?)),
+            NumericType::UInt64  => Numeric::UInt64(u64::from_le_bytes(self.array("u64")?)),
+            NumericType::UInt128 => Numeric::UInt128(u128::from_le_bytes(self.array("u128")?)),
+            NumericType::Int8    => Numeric::Int8(i8::from_le_bytes(self.array("i8")?)),
+            NumericType::Int16   => Numeric::Int16(i16::from_le_bytes(self.array("i16")?)),
+            NumericType::Int32   => Numeric::Int32(i32::from_le_bytes(self.array("i32")?)),
+            NumericType::Int64   => Numeric::Int64(i64::from_le_bytes(self.array("i64")?)),
+            NumericType::Int128  => Numeric::Int128(i128::from_le_bytes(self.array("i128")?)),
+            NumericType::Float32 => Numeric::Float32(f32::from_bits(u32::from_le_bytes(self.array("f32")?))),
+            NumericType::Float64 => Numeric::Float64(f64::from_bits(u64::from_le_bytes(self.array("f64")?))),
+            NumericType::USize   => {
+                let value = u64::from_le_bytes(self.array("usize")?);
+
+                Numeric::USize(usize::try_from(value)
+                    .map_err(|_| DecodeError::new(start, "usize value does not fit on this platform"))?)
+            },
+            NumericType::ISize   => {
+                let value = i64::from_le_bytes(self.array("isize")?);
+
+                Numeric::ISize(isize::try_from(value)
+                    .map_err(|_| DecodeError::new(start, "isize value does not fit on this platform"))?)
+            },
+        })
+    }
+
+    fn data_type(&mut self) -> Result<DataType, DecodeError> {
+        let tag = self.byte("data type")?;
+
+        Ok(match tag {
+            DATA_TYPE_STR => DataType::Str,
+            DATA_TYPE_NUMERIC => DataType::Numeric(self.numeric_type()?),
+            DATA_TYPE_BOOL => DataType::Bool,
+            DATA_TYPE_PTR => DataType::Ptr,
+            DATA_TYPE_TYPE => DataType::Type,
+            DATA_TYPE_ARRAY => DataType::Array,
+            DATA_TYPE_MAP => DataType::Map,
+            DATA_TYPE_STRUCT => DataType::Struct,
+            _ => return Err(DecodeError::new(self.offset - 1, &format!("Unknown data type tag {}", tag)))
+        })
+    }
+
+    fn value(&mut self) -> Result<Value, DecodeError> {
+        let tag = self.byte("value tag")?;
+
+        Ok(match tag {
+            VALUE_STR => Value::Str(self.string("string")?),
+            VALUE_NUMERIC => Value::Numeric(self.numeric()?),
+            VALUE_BOOL => match self.byte("bool")? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                other => return Err(DecodeError::new(self.offset - 1, &format!("Invalid bool byte {}", other)))
+            },
+            VALUE_PTR => Value::Ptr(self.ptr()?),
+            VALUE_TYPE => Value::Type(self.data_type()?),
+            VALUE_ARRAY | VALUE_MAP | VALUE_STRUCT => {
+                if self.depth == MAX_VALUE_DEPTH {
+                    return Err(DecodeError::new(self.offset - 1, "Arrays, maps and structs are nested too deeply"));
+                }
+
+                self.depth += 1;
+
+                let value = match tag {
+                    VALUE_ARRAY => self.array_value()?,
+                    VALUE_MAP => self.map_value()?,
+                    _ => self.struct_value()?
+                };
+
+                self.depth -= 1;
+
+                value
+            },
+            _ => return Err(DecodeError::new(self.offset - 1, &format!("Unknown value tag {}", tag)))
+        })
+    }
+
+    fn array_value(&mut self) -> Result<Value, DecodeError> {
+        let count = self.count("array items")?;
+        let mut items = vec![];
+
+        for _ in 0..count {
+            items.push(self.value()?);
+        }
+
+        Ok(Value::Array(Rc::new(items)))
+    }
+
+    fn map_value(&mut self) -> Result<Value, DecodeError> {
+        let count = self.count("map entries")?;
+        let mut entries = HashMap::new();
+
+        for _ in 0..count {
+            let start = self.offset;
+            let key = MapKey::from_value(&self.value()?).map_err(|error| DecodeError::new(start, &error.message))?;
+
+            if entries.insert(key, self.value()?).is_some() {
+                return Err(DecodeError::new(start, "Duplicate map key"));
+            }
+        }
+
+        Ok(Value::Map(Rc::new(entries)))
+    }
+
+    fn struct_value(&mut self) -> Result<Value, DecodeError> {
+        let start = self.offset;
+        let name = self.string("struct name")?;
+
+        let definition = self.structs.get(&name)
+            .cloned()
+            .ok_or_else(|| DecodeError::new(start, &format!("Unknown struct type '{}'", name)))?;
+
+        let mut fields = vec![];
+
+        for (field, data_type) in &definition.fields {
+            let start = self.offset;
+            let value = self.value()?;
+
+            if value.get_type() != *data_type {
+                let message = format!("Field {} of {} is of type {}, found {}", field, name, data_type, value.get_type());
+                return Err(DecodeError::new(start, &message));
+            }
+
+            fields.push(value);
+        }
+
+        Ok(Value::Struct(Rc::new(Struct { definition, fields })))
+    }
+
+    fn field(&mut self) -> Result<Field, DecodeError> {
+        let tag = self.byte("field tag")?;
+
+        Ok(match tag {
+            FIELD_NAME => Field::Name(self.string("field name")?),
+            FIELD_INDEX => Field::Index(self.usize("field index")?),
+            _ => return Err(DecodeError::new(self.offset - 1, &format!("Unknown field tag {}", tag)))
+        })
+    }
+
+    fn struct_definition(&mut self) -> Result<StructDef, DecodeError> {
+        let name = self.string("struct name")?;
+        let mut fields: Vec<(String, DataType)> = vec![];
+
+        for _ in 0..self.count("struct fields")? {
+            let start = self.offset;
+            let field = self.string("field name")?;
+
+            if fields.iter().any(|(existing, _)| *existing == field) {
+                return Err(DecodeError::new(start, &format!("Duplicate field '{}' in struct '{}'", field, name)));
+            }
+
+            fields.push((field, self.data_type()?));
+        }
+
+        Ok(StructDef { name, fields })
+    }
+
+    fn value_type(&mut self) -> Result<ValueType, DecodeError> {
+        let tag = self.byte("operand tag")?;
+
+        Ok(match tag {
+            VALUE_TYPE_STACK => ValueType::StackValue,
+            VALUE_TYPE_PTR => ValueType::Ptr(self.ptr()?),
+            VALUE_TYPE_VALUE => ValueType::Value(self.value()?),
+            VALUE_TYPE_LOCAL => ValueType::Local(self.usize("local index")?),
+            _ => return Err(DecodeError::new(self.offset - 1, &format!("Unknown operand tag {}", tag)))
+        })
+    }
+
+    fn jump_target(&mut self) -> Result<JumpTarget, DecodeError> {
+        let tag = self.byte("jump target tag")?;
+
+        Ok(match tag {
+            JUMP_ABSOLUTE => JumpTarget::Absolute(self.usize("jump target")?),
+            JUMP_RELATIVE => {
+                let start = self.offset;
+                let zigzag = self.varint("jump offset")?;
+                let offset = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+
+                JumpTarget::Relative(isize::try_from(offset)
+                    .map_err(|_| DecodeError::new(start, "Jump offset does not fit in isize"))?)
+            },
+            _ => return Err(DecodeError::new(self.offset - 1, &format!("Unknown jump target tag {}", tag)))
+        })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, DecodeError> {
+        let start = self.offset;
+        let family = self.byte("instruction family")?;
+        let op = self.byte("instruction opcode")?;
+
+        Ok(match (family, op) {
+            (FAMILY_STACK, 0)   => Instruction::Stack(StackOp::Swap),
+            (FAMILY_STACK, 1)   => Instruction::Stack(StackOp::Duplicate),
+            (FAMILY_STACK, 2)   => Instruction::Stack(StackOp::Drop),
+            (FAMILY_STACK, 3)   => Instruction::Stack(StackOp::Pop(self.ptr()?)),
+            (FAMILY_STACK, 4)   => Instruction::Stack(StackOp::Push(self.value_type()?)),
+            (FAMILY_STACK, 5)   => Instruction::Stack(StackOp::PushPtr(self.ptr()?)),
+            (FAMILY_STACK, 6)   => Instruction::Stack(StackOp::DeRef),
+            (FAMILY_STACK, 7)   => Instruction::Stack(StackOp::SubStack(self.value_type()?)),
+            (FAMILY_STACK, 8)   => Instruction::Stack(StackOp::Destack(self.value_type()?)),
+            (FAMILY_STACK, 9)   => Instruction::Stack(StackOp::Len),
+            (FAMILY_STACK, 10)  => Instruction::Stack(StackOp::Inspect),
+            (FAMILY_STACK, 11)  => Instruction::Stack(StackOp::Alloc),
+            (FAMILY_STACK, 12)  => Instruction::Stack(StackOp::Store),
+            (FAMILY_STACK, 13)  => Instruction::Stack(StackOp::StoreLocal(self.usize("local index")?)),
+            (FAMILY_MATH, 0)    => Instruction::Math(MathOp::Add),
+            (FAMILY_MATH, 1)    => Instruction::Math(MathOp::Sub),
+            (FAMILY_MATH, 2)    => Instruction::Math(MathOp::Mul),
+            (FAMILY_MATH, 3)    => Instruction::Math(MathOp::Div),
+            (FAMILY_MATH, 4)    => Instruction::Math(MathOp::GreaterThan),
+            (FAMILY_MATH, 5)    => Instruction::Math(MathOp::LessThan),
+            (FAMILY_MATH, 6)    => Instruction::Math(MathOp::GreaterThanEq),
+            (FAMILY_MATH, 7)    => Instruction::Math(MathOp::LessThanEq),
+            (FAMILY_MATH, 8)    => Instruction::Math(MathOp::Eql),
+            (FAMILY_MATH, 9)    => Instruction::Math(MathOp::Rem),
+            (FAMILY_MATH, 10)   => Instruction::Math(MathOp::Neg),
+            (FAMILY_MATH, 11)   => Instruction::Math(MathOp::Abs),
+            (FAMILY_MATH, 12)   => Instruction::Math(MathOp::BitAnd),
+            (FAMILY_MATH, 13)   => Instruction::Math(MathOp::BitOr),
+            (FAMILY_MATH, 14)   => Instruction::Math(MathOp::BitXor),
+            (FAMILY_MATH, 15)   => Instruction::Math(MathOp::Not),
+            (FAMILY_MATH, 16)   => Instruction::Math(MathOp::Shl),
+            (FAMILY_MATH, 17)   => Instruction::Math(MathOp::Shr),
+            (FAMILY_MATH, 18)   => Instruction::Math(MathOp::RotateLeft),
+            (FAMILY_MATH, 19)   => Instruction::Math(MathOp::RotateRight),
+            (FAMILY_MATH, 20)   => Instruction::Math(MathOp::Min),
+            (FAMILY_MATH, 21)   => Instruction::Math(MathOp::Max),
+            (FAMILY_MATH, 22)   => Instruction::Math(MathOp::Pow),
+            (FAMILY_MATH, 23)   => Instruction::Math(MathOp::WrappingAdd),
+            (FAMILY_MATH, 24)   => Instruction::Math(MathOp::WrappingSub),
+            (FAMILY_MATH, 25)   => Instruction::Math(MathOp::WrappingMul),
+            (FAMILY_MATH, 26)   => Instruction::Math(MathOp::WrappingDiv),
+            (FAMILY_MATH, 27)   => Instruction::Math(MathOp::WrappingRem),
+            (FAMILY_MATH, 28)   => Instruction::Math(MathOp::WrappingNeg),
+            (FAMILY_MATH, 29)   => Instruction::Math(MathOp::WrappingAbs),
+            (FAMILY_MATH, 30)   => Instruction::Math(MathOp::WrappingPow),
+            (FAMILY_MATH, 31)   => Instruction::Math(MathOp::SaturatingAdd),
+            (FAMILY_MATH, 32)   => Instruction::Math(MathOp::SaturatingSub),
+            (FAMILY_MATH, 33)   => Instruction::Math(MathOp::SaturatingMul),
+            (FAMILY_MATH, 34)   => Instruction::Math(MathOp::SaturatingDiv),
+            (FAMILY_MATH, 35)   => Instruction::Math(MathOp::SaturatingRem),
+            (FAMILY_MATH, 36)   => Instruction::Math(MathOp::SaturatingNeg),
+            (FAMILY_MATH, 37)   => Instruction::Math(MathOp::SaturatingAbs),
+            (FAMILY_MATH, 38)   => Instruction::Math(MathOp::SaturatingPow),
+            (FAMILY_TYPE, 0)    => Instruction::Type(TypeOp::NumericCast(self.numeric_type()?)),
+            (FAMILY_TYPE, 1)    => Instruction::Type(TypeOp::TypeOf),
+            (FAMILY_TYPE, 2)    => Instruction::Type(TypeOp::Is(self.data_type()?)),
+            (FAMILY_TYPE, 3)    => Instruction::Type(TypeOp::CheckedCast(self.numeric_type()?)),
+            (FAMILY_TYPE, 4)    => Instruction::Type(TypeOp::SaturatingCast(self.numeric_type()?)),
+            (FAMILY_CONTROL, 0) => Instruction::Control(ControlOp::Call(self.value_type()?)),
+            (FAMILY_CONTROL, 1) => Instruction::Control(ControlOp::CallIf(self.value_type()?, self.value_type()?)),
+            (FAMILY_CONTROL, 2) => Instruction::Control(ControlOp::CallElse(self.value_type()?, self.value_type()?)),
+            (FAMILY_CONTROL, 3) => Instruction::Control(ControlOp::Return),
+            (FAMILY_CONTROL, 4) => Instruction::Control(ControlOp::ReturnIf(self.value_type()?)),
+            (FAMILY_CONTROL, 5) => Instruction::Control(ControlOp::Jump(self.jump_target()?)),
+            (FAMILY_CONTROL, 6) => Instruction::Control(ControlOp::JumpIf(self.jump_target()?, self.value_type()?)),
+            (FAMILY_CONTROL, 7) => Instruction::Control(ControlOp::JumpElse(self.jump_target()?, self.value_type()?)),
+            (FAMILY_CONTROL, 8) => Instruction::Control(ControlOp::TailCall(self.value_type()?)),
+            (FAMILY_STR, 0)     => Instruction::Str(StrOp::Concat),
+            (FAMILY_STR, 1)     => Instruction::Str(StrOp::Len),
+            (FAMILY_STR, 2)     => Instruction::Str(StrOp::CharLen),
+            (FAMILY_STR, 3)     => Instruction::Str(StrOp::Substring),
+            (FAMILY_STR, 4)     => Instruction::Str(StrOp::IndexOf),
+            (FAMILY_STR, 5)     => Instruction::Str(StrOp::Split),
+            (FAMILY_STR, 6)     => Instruction::Str(StrOp::Join),
+            (FAMILY_STR, 7)     => Instruction::Str(StrOp::Trim),
+            (FAMILY_STR, 8)     => Instruction::Str(StrOp::Upper),
+            (FAMILY_STR, 9)     => Instruction::Str(StrOp::Lower),
+            (FAMILY_STR, 10)    => Instruction::Str(StrOp::StartsWith),
+            (FAMILY_STR, 11)    => Instruction::Str(StrOp::EndsWith),
+            (FAMILY_STR, 12)    => Instruction::Str(StrOp::Eql),
+            (FAMILY_STR, 13)    => Instruction::Str(StrOp::GreaterThan),
+            (FAMILY_STR, 14)    => Instruction::Str(StrOp::LessThan),
+            (FAMILY_STR, 15)    => Instruction::Str(StrOp::GreaterThanEq),
+            (FAMILY_STR, 16)    => Instruction::Str(StrOp::LessThanEq),
+            (FAMILY_STR, 17)    => Instruction::Str(StrOp::Format),
+            (FAMILY_STR, 18)    => Instruction::Str(StrOp::Parse(self.numeric_type()?)),
+            (FAMILY_LOGIC, 0)   => Instruction::Logic(LogicOp::And),
+            (FAMILY_LOGIC, 1)   => Instruction::Logic(LogicOp::Or),
+            (FAMILY_LOGIC, 2)   => Instruction::Logic(LogicOp::Xor),
+            (FAMILY_LOGIC, 3)   => Instruction::Logic(LogicOp::Not),
+            (FAMILY_LOGIC, 4)   => Instruction::Logic(LogicOp::Eq),
+            (FAMILY_LOGIC, 5)   => Instruction::Logic(LogicOp::Ne),
+            (FAMILY_ARRAY, 0)   => Instruction::Array(ArrayOp::New),
+            (FAMILY_ARRAY, 1)   => Instruction::Array(ArrayOp::Push),
+            (FAMILY_ARRAY, 2)   => Instruction::Array(ArrayOp::Pop),
+            (FAMILY_ARRAY, 3)   => Instruction::Array(ArrayOp::Get),
+            (FAMILY_ARRAY, 4)   => Instruction::Array(ArrayOp::Set),
+            (FAMILY_ARRAY, 5)   => Instruction::Array(ArrayOp::Len),
+            (FAMILY_ARRAY, 6)   => Instruction::Array(ArrayOp::Slice),
+            (FAMILY_ARRAY, 7)   => Instruction::Array(ArrayOp::Pack),
+            (FAMILY_ARRAY, 8)   => Instruction::Array(ArrayOp::Unpack),
+            (FAMILY_MAP, 0)     => Instruction::Map(MapOp::New),
+            (FAMILY_MAP, 1)     => Instruction::Map(MapOp::Insert),
+            (FAMILY_MAP, 2)     => Instruction::Map(MapOp::Get),
+            (FAMILY_MAP, 3)     => Instruction::Map(MapOp::GetOr),
+            (FAMILY_MAP, 4)     => Instruction::Map(MapOp::Remove),
+            (FAMILY_MAP, 5)     => Instruction::Map(MapOp::Contains),
+            (FAMILY_MAP, 6)     => Instruction::Map(MapOp::Len),
+            (FAMILY_MAP, 7)     => Instruction::Map(MapOp::Keys),
+            (FAMILY_MAP, 8)     => Instruction::Map(MapOp::Values),
+            (FAMILY_STRUCT, 0)  => Instruction::Struct(StructOp::New(self.string("struct name")?)),
+            (FAMILY_STRUCT, 1)  => Instruction::Struct(StructOp::Get(self.field()?)),
+            (FAMILY_STRUCT, 2)  => Instruction::Struct(StructOp::Set(self.field()?)),
+            _ => return Err(DecodeError::new(start, &format!("Unknown instruction {}:{}", family, op)))
+        })
+    }
+
+    fn function(&mut self) -> Result<(usize, Function), DecodeError> {
+        let id = self.usize("function id")?;
+        let param_count = self.usize("param count")?;
+        let return_count = self.usize("return count")?;
+
+        let mut ptr_recipie = vec![];
+        for _ in 0..self.count("ptr recipe")? {
+            ptr_recipie.push(self.value()?);
+        }
+
+        let mut instructions = vec![];
+        for _ in 0..self.count("instructions")? {
+            instructions.push(self.instruction()?);
+        }
+
+        Ok((id, Function { ptr_recipie, instructions, param_count, return_count }))
+    }
+}
+
+
+pub fn decode(bytes: &[u8]) -> Result<Program, DecodeError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(DecodeError::new(bytes.len(), "File too short to contain a header"));
+    }
+
+    if &bytes[0..4] != MAGIC {
+        return Err(DecodeError::new(0, "Not a bytecode file (bad magic number)"));
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+
+    if version != VERSION {
+        return Err(DecodeError::new(4, &format!("Unsupported format version {} (expected {})", version, VERSION)));
+    }
+
+    let length = u32::from_le_bytes(bytes[6..10].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(bytes[10..14].try_into().unwrap());
+    let payload = &bytes[HEADER_LEN..];
+
+    if payload.len() != length {
+        return Err(DecodeError::new(6, &format!("Payload length {} does not match header length {}", payload.len(), length)));
+    }
+
+    if crc32(payload) != checksum {
+        return Err(DecodeError::new(10, "Checksum mismatch, file is corrupt"));
+    }
+
+    let mut decoder = Decoder {
+        bytes,
+        offset: HEADER_LEN,
+        ptrs: vec![],
+        structs: HashMap::new(),
+        depth: 0
+    };
+
+    // Structs come first so values anywhere after them may be struct instances.
+    for _ in 0..decoder.count("structs")? {
+        let start = decoder.offset;
+        let definition = decoder.struct_definition()?;
+
+        if decoder.structs.contains_key(&definition.name) {
+            return Err(DecodeError::new(start, &format!("Duplicate struct '{}'", definition.name)));
+        }
+
+        decoder.structs.insert(definition.name.clone(), Rc::new(definition));
+    }
+
+    // Ptrs are allocated before their values are read so values may refer to any ptr.
+    let ptr_count = decoder.count("ptrs")?;
+    decoder.ptrs = (0..ptr_count).map(|_| Ptr::new(Value::Bool(false))).collect();
+
+    let mut named_ptrs = vec![];
+    let mut exports = HashSet::new();
+
+    for index in 0..ptr_count {
+        let name = decoder.string("ptr name")?;
+        let exported = match decoder.byte("export flag")? {
+            0 => false,
+            1 if !name.is_empty() => true,
+            other => return Err(DecodeError::new(decoder.offset - 1, &format!("Invalid export flag {}", other)))
+        };
+        let value = decoder.value()?;
+        let ptr = decoder.ptrs[index].clone();
+
+        ptr.value.replace(value);
+
+        if !name.is_empty() {
+            if named_ptrs.iter().any(|(named, _): &(String, Ptr)| *named == name) {
+                return Err(decoder.error(&format!("Duplicate ptr name '{}'", name)));
+            }
+
+            if exported {
+                exports.insert(name.clone());
+            }
+
+            named_ptrs.push((name, ptr));
+        }
+    }
+
+    let mut labels = HashMap::new();
+
+    for _ in 0..decoder.count("labels")? {
+        let name = decoder.string("label name")?;
+        let id = decoder.usize("label id")?;
+
+        if labels.insert(name.clone(), id).is_some() {
+            return Err(decoder.error(&format!("Duplicate label '{}'", name)));
+        }
+    }
+
+    let mut functions = HashMap::new();
+
+    for _ in 0..decoder.count("functions")? {
+        let start = decoder.offset;
+        let (id, function) = decoder.function()?;
+
+        if functions.insert(id, function).is_some() {
+            return Err(DecodeError::new(start, &format!("Duplicate function id {}", id)));
+        }
+    }
+
+    if decoder.offset != bytes.len() {
+        return Err(decoder.error("Trailing data after last function"));
+    }
+
+    let mut label_ids = HashSet::new();
+
+    for (name, id) in &labels {
+        if !functions.contains_key(id) {
+            return Err(DecodeError::new(HEADER_LEN, &format!("Label '{}' refers to missing function {}", name, id)));
+        }
+
+        if !label_ids.insert(*id) {
+            return Err(DecodeError::new(HEADER_LEN, &format!("Function {} has more than one label", id)));
+        }
+    }
+
+    Ok(Program {
+        functions,
+        structs: decoder.structs,
+        labels,
+        ptrs: named_ptrs,
+        exports
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program_with_ptr(value: Value) -> Program {
+        let mut program = Program::new(HashMap::new());
+        program.ptrs.push(("x".to_string(), Ptr::new(value)));
+
+        program
+    }
+
+    fn nested(depth: usize) -> Value {
+        (0..depth).fold(Value::Bool(true), |value, _| Value::Array(Rc::new(vec![value])))
+    }
+
+    #[test]
+    fn nesting_is_capped_on_both_sides() {
+        let bytes = encode(&program_with_ptr(nested(MAX_VALUE_DEPTH))).unwrap();
+        let program = decode(&bytes).unwrap();
+
+        assert!(program.ptrs[0].1.value.borrow().equals(&nested(MAX_VALUE_DEPTH)));
+
+        let error = encode(&program_with_ptr(nested(MAX_VALUE_DEPTH + 1))).unwrap_err();
+        assert_eq!(error.to_string(), "Ptr x: Arrays, maps and structs are nested too deeply");
+    }
+
+    // A header for `payload` that passes the length and checksum checks.
+    fn sealed(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32(payload).to_le_bytes());
+        bytes.extend_from_slice(payload);
+
+        bytes
+    }
+
+    fn decode_error(bytes: &[u8]) -> (usize, String) {
+        match decode(bytes) {
+            Ok(_) => panic!("decoding should fail"),
+            Err(error) => (error.offset, error.message)
+        }
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let bytes = encode(&program_with_ptr(Value::Numeric(Numeric::Int32(7)))).unwrap();
+        let payload = &bytes[HEADER_LEN..];
+        assert!(decode(&sealed(payload)).is_ok());
+
+        assert_eq!(decode_error(&bytes[..HEADER_LEN - 1]), (HEADER_LEN - 1, "File too short to contain a header".to_string()));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(decode_error(&bad_magic).0, 0);
+
+        let mut bad_version = bytes.clone();
+        bad_version[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(decode_error(&bad_version), (4, format!("Unsupported format version {} (expected {})", VERSION + 1, VERSION)));
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(decode_error(&corrupt), (10, "Checksum mismatch, file is corrupt".to_string()));
+
+        assert_eq!(decode_error(&bytes[..bytes.len() - 1]).0, 6);
+
+        // Cut short or padded payloads that still pass the checksum fail while decoding.
+        let truncated = decode_error(&sealed(&payload[..payload.len() - 1])).1;
+        assert!(truncated.starts_with("Unexpected end of data"), "{}", truncated);
+
+        let mut padded = payload.to_vec();
+        padded.push(0);
+        assert_eq!(decode_error(&sealed(&padded)), (bytes.len(), "Trailing data after last function".to_string()));
+    }
+
+    // The variant and raw bits, so NaN payloads and negative zero compare exactly.
+    fn bits(numeric: &Numeric) -> (u8, u128) {
+        match numeric {
+            Numeric::UInt8(n)   => (0, *n as u128),
+            Numeric::UInt16(n)  => (1, *n as u128),
+            Numeric::UInt32(n)  => (2, *n as u128),
+            Numeric::UInt64(n)  => (3, *n as u128),
+            Numeric::UInt128(n) => (4, *n),
+            Numeric::Int8(n)    => (5, *n as u8 as u128),
+            Numeric::Int16(n)   => (6, *n as u16 as u128),
+            Numeric::Int32(n)   => (7, *n as u32 as u128),
+            Numeric::Int64(n)   => (8, *n as u64 as u128),
+            Numeric::Int128(n)  => (9, *n as u128),
+            Numeric::Float32(n) => (10, n.to_bits() as u128),
+            Numeric::Float64(n) => (11, n.to_bits() as u128),
+            Numeric::USize(n)   => (12, *n as u128),
+            Numeric::ISize(n)   => (13, *n as usize as u128),
+        }
+    }
+
+    #[test]
+    fn numerics_round_trip_bit_for_bit() {
+        let numerics = vec![
+            Numeric::UInt8(u8::MAX),
+            Numeric::UInt16(u16::MAX),
+            Numeric::UInt32(u32::MAX),
+            Numeric::UInt64(u64::MAX),
+            Numeric::UInt128(u128::MAX),
+            Numeric::Int8(i8::MIN),
+            Numeric::Int16(i16::MIN),
+            Numeric::Int32(i32::MIN),
+            Numeric::Int64(i64::MIN),
+            Numeric::Int128(i128::MIN),
+            Numeric::Int128(i128::MAX),
+            Numeric::Float32(f32::from_bits(0x7fc0_1234)),
+            Numeric::Float32(f32::from_bits(0xff80_0001)),
+            Numeric::Float32(-0.0),
+            Numeric::Float64(f64::from_bits(0x7ff8_0000_dead_beef)),
+            Numeric::Float64(f64::NEG_INFINITY),
+            Numeric::Float64(f64::MIN_POSITIVE / 2.0),
+            Numeric::USize(usize::MAX),
+            Numeric::ISize(isize::MIN),
+        ];
+
+        let values = numerics.iter().cloned().map(Value::Numeric).collect();
+        let program = decode(&encode(&program_with_ptr(Value::Array(Rc::new(values)))).unwrap()).unwrap();
+
+        let decoded = match &*program.ptrs[0].1.value.borrow() {
+            Value::Array(values) => values.iter().map(|value| match value {
+                Value::Numeric(numeric) => bits(numeric),
+                other => panic!("decoded {} instead of a numeric", other)
+            }).collect::<Vec<_>>(),
+            other => panic!("decoded {} instead of an array", other)
+        };
+
+        assert_eq!(decoded, numerics.iter().map(bits).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,397 @@
+use std::fs;
+use std::path::Path;
+
+use crate::value::Value;
+use crate::program::Program;
+use crate::function::FunctionController;
+use crate::stack::format_values;
+use crate::numeric::Promotion;
+use crate::tracer::JsonTracer;
+use crate::assembler;
+use crate::disassembler;
+use crate::bytecode;
+use crate::repl;
+use crate::debug_shell;
+
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_VM_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_LOAD_ERROR: i32 = 3;
+
+
+const USAGE: &str = "\
+Usage:
+    vm run <file> [--entry <name|id>] [args...]   Run a program, pushing args onto the entry stack
+        [--trace <out>]                           and writing a JSON Lines execution trace to <out>
+        [--promote <strict|widen|float64>]        with mixed numeric operands promoted, see readme
+        [--heap-stats]                            and printing garbage collector stats to stderr
+    vm asm <file> [-o <out>]                      Assemble a .vmasm file into bytecode
+    vm disasm <file>                              Print a program as assembly
+    vm check <file> [--entry <name|id>]           Load and validate a program without running it
+    vm repl                                       Start an interactive session
+    vm debug <file> [--entry <name|id>] [args...] Step through a program with breakpoints
+
+Files may be either .vmasm text or bytecode. Arguments are typed literals such as 42i32,
+3.0f64, true or '\"str\"'; anything else is passed as a string.
+
+Exit codes: 0 success, 1 VM or validation error, 2 usage error, 3 load error.";
+
+
+struct Options {
+    positional: Vec<String>,
+    entry: Option<String>,
+    output: Option<String>,
+    trace: Option<String>,
+    promotion: Promotion,
+    heap_stats: bool
+}
+
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        positional: vec![],
+        entry: None,
+        output: None,
+        trace: None,
+        promotion: Promotion::Strict,
+        heap_stats: false
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" | "--entry" => {
+                options.entry = Some(args.next().ok_or("Missing value for --entry")?.clone());
+            },
+            "-o" | "--output" => {
+                options.output = Some(args.next().ok_or("Missing value for --output")?.clone());
+            },
+            "-t" | "--trace" => {
+                options.trace = Some(args.next().ok_or("Missing value for --trace")?.clone());
+            },
+            "-p" | "--promote" => {
+                let name = args.next().ok_or("Missing value for --promote")?;
+                options.promotion = Promotion::from_name(name).ok_or(format!("Unknown promotion '{}', expected strict, widen or float64", name))?;
+            },
+            "--heap-stats" => options.heap_stats = true,
+            "--" => {
+                options.positional.extend(args.by_ref().cloned());
+            },
+            _ => options.positional.push(arg.clone())
+        }
+    }
+
+    Ok(options)
+}
+
+
+pub fn load(path: &str) -> Result<Program, String> {
+    let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+
+    if bytes.starts_with(bytecode::MAGIC) {
+        bytecode::decode(&bytes).map_err(|error| format!("{}: {}", path, error))
+    } else {
+        let source = String::from_utf8(bytes).map_err(|_| format!("{}: not valid UTF-8 assembly or bytecode", path))?;
+
+        assembler::assemble(&source).map_err(|error| format!("{}:{}", path, error))
+    }
+}
+
+
+pub fn entry(program: &Program, entry: Option<&str>) -> Result<usize, String> {
+    let id = match entry {
+        Some(entry) => match entry.parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => program.function_id(entry).ok_or(format!("No function named '{}'", entry))?
+        },
+        None => match program.function_id("main") {
+            Some(id) => id,
+            None if program.functions.len() == 1 => *program.functions.keys().next().unwrap(),
+            None => return Err("No 'main' function, choose one with --entry".to_string())
+        }
+    };
+
+    if program.functions.contains_key(&id) {
+        Ok(id)
+    } else {
+        Err(format!("No function with id {}", id))
+    }
+}
+
+
+fn parse_arg(arg: &str) -> Result<Value, String> {
+    match assembler::parse_value(arg) {
+        Ok(value) => Ok(value),
+        Err(_) if !arg.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '"') => Ok(Value::Str(arg.to_string())),
+        Err(error) => Err(format!("Invalid argument '{}': {}", arg, error.message))
+    }
+}
+
+
+fn expect_file(options: &Options, command: &str) -> Result<String, String> {
+    options.positional.first()
+        .cloned()
+        .ok_or(format!("'{}' needs a file", command))
+}
+
+
+// Loads the program named on the command line and builds a controller for its entry function,
+// with the remaining arguments already pushed.
+fn prepare(options: &Options, command: &str) -> Result<(Program, FunctionController), (i32, String)> {
+    let path = expect_file(options, command).map_err(|error| (EXIT_USAGE, error))?;
+    let mut program = load(&path).map_err(|error| (EXIT_LOAD_ERROR, error))?;
+    let start = entry(&program, options.entry.as_deref()).map_err(|error| (EXIT_USAGE, error))?;
+
+    let args = options.positional[1..].iter()
+        .map(|arg| parse_arg(arg))
+        .collect::<Result<Vec<Value>, String>>()
+        .map_err(|error| (EXIT_USAGE, error))?;
+
+    let tracer = match &options.trace {
+        Some(path) => {
+            let file = fs::File::create(path).map_err(|error| (EXIT_LOAD_ERROR, format!("{}: {}", path, error)))?;
+
+            let lines = program.functions.keys()
+                .map(|id| (*id, disassembler::function_lines(&program.functions, &program.labels, &program.ptrs, *id)))
+                .collect();
+
+            Some(JsonTracer::new(std::io::BufWriter::new(file), lines))
+        },
+        None => None
+    };
+
+    let mut controller = FunctionController::new(std::mem::take(&mut program.functions), start);
+    controller.set_promotion(options.promotion);
+    controller.set_structs(program.structs.clone());
+
+    for (_, ptr) in &program.ptrs {
+        controller.add_root(ptr.clone());
+    }
+
+    if let Some(tracer) = tracer {
+        controller.set_tracer(Box::new(tracer));
+    }
+
+    for arg in args {
+        controller.push(arg);
+    }
+
+    Ok((program, controller))
+}
+
+
+fn run_program(options: &Options) -> Result<i32, (i32, String)> {
+    let (program, mut controller) = prepare(options, "run")?;
+
+    let result = controller.run();
+
+    if options.heap_stats {
+        eprintln!("heap: {}", controller.heap_stats());
+    }
+
+    if let Err(error) = result {
+        return Err((EXIT_VM_ERROR, error.to_string()));
+    }
+
+    println!("stack: {}", format_values(&controller.stack().current().borrow()));
+
+    for (name, ptr) in program.exported_ptrs() {
+        println!("{} = {}", name, ptr.value.borrow());
+    }
+
+    Ok(EXIT_OK)
+}
+
+
+fn assemble(options: &Options) -> Result<i32, (i32, String)> {
+    let path = expect_file(options, "asm").map_err(|error| (EXIT_USAGE, error))?;
+    let program = load(&path).map_err(|error| (EXIT_LOAD_ERROR, error))?;
+
+    let output = options.output.clone()
+        .unwrap_or_else(|| Path::new(&path).with_extension("vmbc").to_string_lossy().into_owned());
+
+    let bytes = bytecode::encode(&program).map_err(|error| (EXIT_LOAD_ERROR, format!("{}: {}", path, error)))?;
+
+    fs::write(&output, bytes).map_err(|error| (EXIT_LOAD_ERROR, format!("{}: {}", output, error)))?;
+
+    Ok(EXIT_OK)
+}
+
+
+fn disassemble(options: &Options) -> Result<i32, (i32, String)> {
+    let path = expect_file(options, "disasm").map_err(|error| (EXIT_USAGE, error))?;
+    let program = load(&path).map_err(|error| (EXIT_LOAD_ERROR, error))?;
+
+    print!("{}", disassembler::disassemble(&program));
+
+    Ok(EXIT_OK)
+}
+
+
+fn check(options: &Options) -> Result<i32, (i32, String)> {
+    let path = expect_file(options, "check").map_err(|error| (EXIT_USAGE, error))?;
+    let program = load(&path).map_err(|error| (EXIT_LOAD_ERROR, error))?;
+
+    let mut problems = program.validate();
+
+    if let Err(error) = entry(&program, options.entry.as_deref()) {
+        problems.push(error);
+    }
+
+    if !problems.is_empty() {
+        return Err((EXIT_VM_ERROR, problems.join("\n")));
+    }
+
+    println!("{}: ok, {} function(s), {} ptr(s)", path, program.functions.len(), program.ptrs.len());
+
+    Ok(EXIT_OK)
+}
+
+
+pub fn run(args: &[String]) -> i32 {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let options = match parse_options(rest) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let result = match command {
+        "run" => run_program(&options),
+        "asm" => assemble(&options),
+        "disasm" => disassemble(&options),
+        "check" => check(&options),
+        "repl" => Ok(repl::run(std::io::stdin().lock(), &mut std::io::stdout())),
+        "debug" => prepare(&options, "debug").map(|(program, controller)| {
+            debug_shell::run(program, controller, std::io::stdin().lock(), &mut std::io::stdout())
+        }),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(EXIT_OK)
+        },
+        _ => Err((EXIT_USAGE, format!("Unknown command '{}'\n\n{}", command, USAGE)))
+    };
+
+    match result {
+        Ok(code) => code,
+        Err((code, message)) => {
+            eprintln!("{}", message);
+            code
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A file named after the test, so tests running in parallel don't share one.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("vm-cli-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    fn exit_code(args: &[&str]) -> i32 {
+        run(&args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>())
+    }
+
+    const PROGRAM: &str = "
+        fn main params=0 returns=0
+            push 1i32
+        end
+
+        fn add params=2 returns=1
+            add
+        end
+    ";
+
+    #[test]
+    fn assembly_errors_name_the_file() {
+        let path = temp_file("errors.vmasm", "fn main params=0 returns=0\n    frob\nend\n");
+
+        let path = path.to_str().unwrap();
+        let error = load(path).err();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(error, Some(format!("{}:2:5: Unknown instruction 'frob'", path)));
+    }
+
+    #[test]
+    fn exit_codes() {
+        let program = temp_file("exit.vmasm", PROGRAM);
+        let broken = temp_file("exit-broken.vmasm", "fn main params=0 returns=0\n    frob\nend\n");
+        let (program, broken) = (program.to_str().unwrap(), broken.to_str().unwrap());
+
+        assert_eq!(exit_code(&["run", program]), EXIT_OK);
+        assert_eq!(exit_code(&["run", program, "--entry", "add", "1i32", "2i32"]), EXIT_OK);
+        assert_eq!(exit_code(&["check", program]), EXIT_OK);
+
+        // `add` finds one operand when the arguments don't match each other.
+        assert_eq!(exit_code(&["run", program, "--entry", "add", "1i32"]), EXIT_VM_ERROR);
+        assert_eq!(exit_code(&["run", program, "--entry", "add", "1i32", "2u8"]), EXIT_VM_ERROR);
+
+        assert_eq!(exit_code(&[]), EXIT_USAGE);
+        assert_eq!(exit_code(&["frobnicate"]), EXIT_USAGE);
+        assert_eq!(exit_code(&["run"]), EXIT_USAGE);
+        assert_eq!(exit_code(&["run", program, "--entry"]), EXIT_USAGE);
+        assert_eq!(exit_code(&["run", program, "--entry", "missing"]), EXIT_USAGE);
+        assert_eq!(exit_code(&["run", program, "--promote", "sideways"]), EXIT_USAGE);
+        assert_eq!(exit_code(&["run", program, "12q"]), EXIT_USAGE);
+
+        assert_eq!(exit_code(&["run", broken]), EXIT_LOAD_ERROR);
+        assert_eq!(exit_code(&["disasm", "/nonexistent/program.vmasm"]), EXIT_LOAD_ERROR);
+
+        fs::remove_file(program).unwrap();
+        fs::remove_file(broken).unwrap();
+    }
+
+    #[test]
+    fn entries_by_name_or_id() {
+        let program = assembler::assemble(PROGRAM).unwrap();
+
+        assert_eq!(entry(&program, None), Ok(1));
+        assert_eq!(entry(&program, Some("add")), Ok(2));
+        assert_eq!(entry(&program, Some("2")), Ok(2));
+        assert_eq!(entry(&program, Some("3")), Err("No function with id 3".to_string()));
+        assert_eq!(entry(&program, Some("sub")), Err("No function named 'sub'".to_string()));
+
+        // Without `main`, a lone function is the entry, but one of several must be chosen.
+        let single = assembler::assemble("fn only params=0 returns=0\nend\n").unwrap();
+        assert_eq!(entry(&single, None), Ok(1));
+
+        let several = assembler::assemble("fn one params=0 returns=0\nend\nfn two params=0 returns=0\nend\n").unwrap();
+        assert!(entry(&several, None).is_err());
+    }
+
+    #[test]
+    fn args_are_typed_literals_or_strs() {
+        let arg = |text: &str| parse_arg(text).map(|value| value.to_string());
+
+        assert_eq!(arg("42i32"), Ok("42i32".to_string()));
+        assert_eq!(arg("3.0f64"), Ok("3.0f64".to_string()));
+        assert_eq!(arg("-7i8"), Ok("-7i8".to_string()));
+        assert_eq!(arg("true"), Ok("true".to_string()));
+        assert_eq!(arg("\"two words\""), Ok("\"two words\"".to_string()));
+        assert_eq!(arg("hello"), Ok("\"hello\"".to_string()));
+
+        // Anything that looks like a number or a quoted str must be one.
+        assert!(arg("12q").is_err());
+        assert!(arg("300u8").is_err());
+        assert!(arg("\"unterminated").is_err());
+    }
+}
@@ -1,3 +1,38 @@
+use std::fmt;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JumpTarget {
+    Absolute(usize),
+    // Counted from the jump instruction itself, so `Relative(1)` is the next instruction.
+    Relative(isize)
+}
+
+
+impl JumpTarget {
+    pub fn resolve(&self, from: usize) -> Option<usize> {
+        match self {
+            JumpTarget::Absolute(index) => Some(*index),
+            JumpTarget::Relative(offset) => from.checked_add_signed(*offset)
+        }
+    }
+}
+
+
+// Written the way the assembler reads them: `7` or `+2` / `-3`.
+impl fmt::Display for JumpTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JumpTarget::Absolute(index) => write!(f, "{}", index),
+            JumpTarget::Relative(offset) => write!(f, "{:+}", offset)
+        }
+    }
+}
+
+
 pub enum InstructionControl {
-    Call(usize)
+    Call(usize),
+    TailCall(usize),
+    Return,
+    Jump(JumpTarget)
 }
@@ -1,15 +1,48 @@
 use crate::value::{Value, ValueType};
 use crate::numeric::Numeric;
 use crate::instruction::{Runnable, InstructionResult, InstructionError};
-use crate::control::InstructionControl;
+use crate::error::ErrorKind;
+use crate::control::{InstructionControl, JumpTarget};
 use crate::stack::Stack;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ControlOp {
     Call(ValueType),
     CallIf(ValueType, ValueType),
     CallElse(ValueType, ValueType),
+    TailCall(ValueType),
+    Return,
+    ReturnIf(ValueType),
+    Jump(JumpTarget),
+    JumpIf(JumpTarget, ValueType),
+    JumpElse(JumpTarget, ValueType),
+}
+
+
+// Unlike `CallIf` and `CallElse`, a `stack` predicate is popped whether or not the branch is
+// taken, so it never piles up in loops or ends up among returned values.
+fn take_predicate(value: &ValueType, stack: &mut Stack) -> Result<bool, InstructionError> {
+    let predicate = match value.to_value(stack.current().borrow().last()) {
+        Some(Value::Bool(value)) => value,
+        Some(_) => return Err(InstructionError::new(ErrorKind::TypeMismatch, "Predicate value must be boolean")),
+        None => return Err(InstructionError::new(ErrorKind::StackUnderflow, "Failed to obtain predicate value"))
+    };
+
+    if let ValueType::StackValue = value {
+        stack.current().borrow_mut().pop();
+    }
+
+    Ok(predicate)
+}
+
+
+fn branch(taken: Result<bool, InstructionError>, control: InstructionControl) -> InstructionResult {
+    match taken {
+        Ok(true) => InstructionResult::Control(control),
+        Ok(false) => InstructionResult::None,
+        Err(error) => InstructionResult::Error(error)
+    }
 }
 
 
@@ -23,10 +56,10 @@ impl Runnable for ControlOp {
                     if let Value::Numeric(Numeric::USize(value)) = value {
                         InstructionResult::Control(InstructionControl::Call(value))
                     } else {
-                        InstructionResult::Error(InstructionError::new("Oprand must be numeric usize"))
+                        InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Oprand must be numeric usize"))
                     }
                 } else {
-                    InstructionResult::Error(InstructionError::new("Failed to obtian function value"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to obtain function value"))
                 }
             },
             ControlOp::CallIf(ptr, value) => {
@@ -44,16 +77,16 @@ impl Runnable for ControlOp {
                                     InstructionResult::None
                                 }
                             } else {
-                                InstructionResult::Error(InstructionError::new("Predicate value must be boolean"))
+                                InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Predicate value must be boolean"))
                             }
                         } else {
-                            InstructionResult::Error(InstructionError::new("Failed to obtain predicate value"))
+                            InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to obtain predicate value"))
                         }
                     } else {
-                        InstructionResult::Error(InstructionError::new("Oprand must be numeric usize"))
+                        InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Oprand must be numeric usize"))
                     }
                 } else {
-                    InstructionResult::Error(InstructionError::new("Failed to obtian function value"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to obtain function value"))
                 }
             },
             ControlOp::CallElse(ptr, value) => {
@@ -71,18 +104,142 @@ impl Runnable for ControlOp {
                                     InstructionResult::None
                                 }
                             } else {
-                                InstructionResult::Error(InstructionError::new("Predicate value must be boolean"))
+                                InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Predicate value must be boolean"))
                             }
                         } else {
-                            InstructionResult::Error(InstructionError::new("Failed to obtain predicate value"))
+                            InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to obtain predicate value"))
                         }
                     } else {
-                        InstructionResult::Error(InstructionError::new("Oprand must be numeric usize"))
+                        InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Oprand must be numeric usize"))
                     }
                 } else {
-                    InstructionResult::Error(InstructionError::new("Failed to obtian function value"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to obtain function value"))
                 }
+            },
+            ControlOp::TailCall(value) => {
+                match value.to_value(stack.current().borrow().last()) {
+                    Some(Value::Numeric(Numeric::USize(value))) => InstructionResult::Control(InstructionControl::TailCall(value)),
+                    Some(_) => InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Operand must be numeric usize")),
+                    None => InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to obtain function value"))
+                }
+            },
+            ControlOp::Return => InstructionResult::Control(InstructionControl::Return),
+            ControlOp::ReturnIf(value) => branch(take_predicate(value, stack), InstructionControl::Return),
+            ControlOp::Jump(target) => InstructionResult::Control(InstructionControl::Jump(*target)),
+            ControlOp::JumpIf(target, value) => branch(take_predicate(value, stack), InstructionControl::Jump(*target)),
+            ControlOp::JumpElse(target, value) => {
+                branch(take_predicate(value, stack).map(|predicate| !predicate), InstructionControl::Jump(*target))
             }
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::testing::{push, contents};
+    use crate::function::testing::{controller, run};
+
+    fn is_return(result: InstructionResult) -> bool {
+        matches!(result, InstructionResult::Control(InstructionControl::Return))
+    }
+
+    #[test]
+    fn stack_predicates_of_retif_are_popped_either_way() {
+        for predicate in [true, false] {
+            let mut stack = Stack::new();
+            push(&mut stack, Value::Bool(predicate));
+
+            assert_eq!(is_return(ControlOp::ReturnIf(ValueType::StackValue).run(&mut stack)), predicate);
+            assert!(contents(&stack).is_empty());
+        }
+
+        // Unlike `callif`, which leaves its predicate.
+        let mut stack = Stack::new();
+        push(&mut stack, Value::Bool(true));
+
+        let call = ControlOp::CallIf(ValueType::Value(Value::Numeric(Numeric::USize(1))), ValueType::StackValue).run(&mut stack);
+
+        assert!(matches!(call, InstructionResult::Control(InstructionControl::Call(1))));
+        assert_eq!(contents(&stack), ["true"]);
+    }
+
+    #[test]
+    fn returns_carry_back_the_return_values() {
+        let source = "
+            fn main params=0 returns=0
+                call early
+                push false
+                call maybe
+            end
+
+            fn early params=0 returns=1
+                push 1i32
+                push 2i32
+                ret
+                push 3i32
+            end
+
+            fn maybe params=1 returns=1
+                retif stack
+                push 4i32
+            end
+        ";
+
+        assert_eq!(run(source), ["2i32", "false", "4i32"]);
+    }
+
+    #[test]
+    fn jumps_follow_their_predicates() {
+        let source = "
+            fn main params=0 returns=0
+                jmp skip
+                push 0i32
+            skip:
+                push true
+                jmpif taken, stack
+                push 1i32
+            taken:
+                push true
+                jmpelse +2, stack
+                push 2i32
+                push false
+                jmpelse +2, stack
+                push 3i32
+                push 4i32
+            end
+        ";
+
+        assert_eq!(run(source), ["2i32", "4i32"]);
+    }
+
+    #[test]
+    fn jumping_one_past_the_end_returns() {
+        let source = "
+            fn main params=0 returns=0
+                push 1i32
+                jmp +2
+                push 2i32
+            end
+        ";
+
+        assert_eq!(run(source), ["1i32"]);
+    }
+
+    #[test]
+    fn jumping_further_is_an_invalid_target() {
+        for target in ["+3", "-2", "4"] {
+            let source = format!("
+                fn main params=0 returns=0
+                    push 1i32
+                    jmp {}
+                    push 2i32
+                end
+            ", target);
+
+            let error = controller(&source).run().expect_err("the jump should fail");
+            assert_eq!(error.kind, ErrorKind::InvalidJumpTarget, "jmp {}", target);
+        }
+    }
+}
@@ -1,14 +1,60 @@
+use std::fmt;
+
+use crate::numeric::NumericType;
+
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum DataType {
     Str,
-    Int32,
+    Numeric(NumericType),
     Bool,
-    Ptr
+    Ptr,
+    // The type of the tags pushed by `typeof`.
+    Type,
+    Array,
+    Map,
+    // Any struct, whatever its declared type.
+    Struct
 }
 
 
-pub trait Typed {
-    fn get_type(&self) -> DataType;
+impl DataType {
+    // Numeric types are named by their literal suffix.
+    pub fn from_name(name: &str) -> Option<DataType> {
+        match name {
+            "str"    => Some(DataType::Str),
+            "bool"   => Some(DataType::Bool),
+            "ptr"    => Some(DataType::Ptr),
+            "type"   => Some(DataType::Type),
+            "array"  => Some(DataType::Array),
+            "map"    => Some(DataType::Map),
+            "struct" => Some(DataType::Struct),
+            _ => NumericType::from_suffix(name).map(DataType::Numeric)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataType::Str => "str",
+            DataType::Numeric(numeric_type) => numeric_type.suffix(),
+            DataType::Bool => "bool",
+            DataType::Ptr => "ptr",
+            DataType::Type => "type",
+            DataType::Array => "array",
+            DataType::Map => "map",
+            DataType::Struct => "struct"
+        }
+    }
 }
 
 
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+
+pub trait Typed {
+    fn get_type(&self) -> DataType;
+}
@@ -0,0 +1,235 @@
+use std::io::{self, BufRead, Write};
+
+use crate::ptr::Ptr;
+use crate::stack::format_values;
+use crate::function::FunctionController;
+use crate::program::Program;
+use crate::debugger::{Debugger, StopReason};
+use crate::disassembler;
+use crate::cli;
+
+
+const HELP: &str = "\
+    break <fn> <index>    (b) pause before instruction <index> of <fn>, or list breakpoints
+    delete <fn> <index>   (d) remove a breakpoint
+    watch <ptr>           (w) pause after a pop or store writes to <ptr>
+    unwatch <ptr>         stop watching <ptr>
+    step                  (s) run one instruction, entering calls
+    next                  (n) run one instruction, running calls to completion
+    out                   (o) run until the current function returns
+    continue              (c) run until a breakpoint, watchpoint or the end
+    backtrace             (bt) show the call chain
+    stack                 show every substack
+    list                  (l) show the current function
+    print <ptr>           (p) show the value of <ptr>
+    help                  (h) show this message
+    quit                  (q) leave the debugger
+
+Functions are named by label or id.";
+
+
+struct Shell {
+    program: Program,
+    debugger: Debugger
+}
+
+
+impl Shell {
+    fn function_id(&self, name: &str) -> Result<usize, String> {
+        let id = name.parse::<usize>().ok()
+            .or_else(|| self.program.function_id(name))
+            .ok_or(format!("No function named '{}'", name))?;
+
+        if self.debugger.controller().functions().contains_key(&id) {
+            Ok(id)
+        } else {
+            Err(format!("No function with id {}", id))
+        }
+    }
+
+    fn function_name(&self, id: usize) -> String {
+        match self.program.function_name(id) {
+            Some(name) => format!("{} ({})", name, id),
+            None => format!("fn {}", id)
+        }
+    }
+
+    fn ptr(&self, name: &str) -> Result<Ptr, String> {
+        self.program.ptr(name).cloned().ok_or(format!("No ptr named '{}'", name))
+    }
+
+    fn ptr_name(&self, ptr: &Ptr) -> String {
+        self.program.ptrs.iter()
+            .find(|(_, named)| named.address() == ptr.address())
+            .map_or_else(|| format!("{:#x}", ptr.address()), |(name, _)| name.clone())
+    }
+
+    fn lines(&self, id: usize) -> Vec<String> {
+        disassembler::function_lines(self.debugger.controller().functions(), &self.program.labels, &self.program.ptrs, id)
+    }
+
+    fn location(&self, output: &mut impl Write) -> io::Result<()> {
+        match self.debugger.position() {
+            Some((function, index)) => {
+                let line = self.lines(function).get(index).cloned().unwrap_or_default();
+
+                writeln!(output, "{} @ {}: {}", self.function_name(function), index, line)
+            },
+            None => writeln!(output, "program finished")
+        }
+    }
+
+    fn stopped(&self, reason: StopReason, output: &mut impl Write) -> io::Result<()> {
+        match reason {
+            StopReason::Step => {},
+            StopReason::Breakpoint(function, index) => {
+                writeln!(output, "breakpoint {} @ {}", self.function_name(function), index)?;
+            },
+            StopReason::Watchpoint(ptr) => {
+                writeln!(output, "watchpoint {} = {}", self.ptr_name(&ptr), ptr.value.borrow())?;
+            },
+            StopReason::Finished => {
+                writeln!(output, "stack: {}", format_values(&self.debugger.controller().stack().current().borrow()))?;
+            },
+            StopReason::Error(error) => writeln!(output, "error: {}", error)?
+        }
+
+        self.location(output)
+    }
+
+    fn backtrace(&self, output: &mut impl Write) -> io::Result<()> {
+        for (depth, context) in self.debugger.controller().call_chain().iter().rev().enumerate() {
+            writeln!(output, "#{} {} @ {}", depth, self.function_name(context.current_fn), context.current_instruction)?;
+        }
+
+        Ok(())
+    }
+
+    fn stack(&self, output: &mut impl Write) -> io::Result<()> {
+        for (depth, values) in self.debugger.controller().stack().snapshot().iter().enumerate() {
+            writeln!(output, "{}: {}", depth, format_values(values))?;
+        }
+
+        Ok(())
+    }
+
+    fn list(&self, output: &mut impl Write) -> io::Result<()> {
+        let (function, current) = match self.debugger.position() {
+            Some(position) => position,
+            None => return writeln!(output, "program finished")
+        };
+
+        writeln!(output, "{}", self.function_name(function))?;
+
+        for (index, line) in self.lines(function).iter().enumerate() {
+            let marker = if index == current { "=>" } else { "  " };
+            let breakpoint = if self.debugger.breakpoints().any(|point| *point == (function, index)) { "*" } else { " " };
+
+            writeln!(output, "{}{} {:>3}  {}", marker, breakpoint, index, line)?;
+        }
+
+        Ok(())
+    }
+
+    fn breakpoint(&self, arguments: &[&str]) -> Result<(usize, usize), String> {
+        match arguments {
+            [function, index] => {
+                let function = self.function_id(function)?;
+                let index = index.parse::<usize>().map_err(|_| format!("Invalid instruction index '{}'", index))?;
+
+                Ok((function, index))
+            },
+            _ => Err("Expected <fn> <index>".to_string())
+        }
+    }
+
+    // Returns false when the session should end.
+    fn command(&mut self, line: &str, output: &mut impl Write) -> io::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => return Ok(true)
+        };
+
+        let result: Result<(), String> = match (command, arguments) {
+            ("quit", _) | ("q", _) => return Ok(false),
+            ("help", _) | ("h", _) => { writeln!(output, "{}", HELP)?; Ok(()) },
+            ("step", _) | ("s", _) => { let reason = self.debugger.step(); self.stopped(reason, output)?; Ok(()) },
+            ("next", _) | ("n", _) => { let reason = self.debugger.step_over(); self.stopped(reason, output)?; Ok(()) },
+            ("out", _) | ("o", _) => { let reason = self.debugger.step_out(); self.stopped(reason, output)?; Ok(()) },
+            ("continue", _) | ("c", _) => { let reason = self.debugger.resume(); self.stopped(reason, output)?; Ok(()) },
+            ("backtrace", _) | ("bt", _) => { self.backtrace(output)?; Ok(()) },
+            ("stack", _) => { self.stack(output)?; Ok(()) },
+            ("list", _) | ("l", _) => { self.list(output)?; Ok(()) },
+            ("break", []) | ("b", []) => {
+                for (function, index) in self.debugger.breakpoints() {
+                    writeln!(output, "{} @ {}", self.function_name(*function), index)?;
+                }
+
+                Ok(())
+            },
+            ("break", arguments) | ("b", arguments) => {
+                self.breakpoint(arguments).and_then(|(function, index)| self.debugger.add_breakpoint(function, index).map(|_| ()))
+            },
+            ("delete", arguments) | ("d", arguments) => {
+                self.breakpoint(arguments).and_then(|(function, index)| {
+                    if self.debugger.remove_breakpoint(function, index) { Ok(()) } else { Err("No such breakpoint".to_string()) }
+                })
+            },
+            ("watch", [name]) | ("w", [name]) => self.ptr(name).map(|ptr| self.debugger.watch(ptr)),
+            ("unwatch", [name]) => {
+                self.ptr(name).and_then(|ptr| {
+                    if self.debugger.unwatch(&ptr) { Ok(()) } else { Err(format!("'{}' is not watched", name)) }
+                })
+            },
+            ("print", [name]) | ("p", [name]) => {
+                match self.ptr(name) {
+                    Ok(ptr) => { writeln!(output, "{} = {}", name, ptr.value.borrow())?; Ok(()) },
+                    Err(error) => Err(error)
+                }
+            },
+            _ => Err(format!("Unknown command '{}', try help", line.trim()))
+        };
+
+        if let Err(error) = result {
+            writeln!(output, "error: {}", error)?;
+        }
+
+        Ok(true)
+    }
+
+    fn session(&mut self, input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+        self.location(output)?;
+
+        write!(output, "(vm) ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            if !self.command(&line?, output)? {
+                return Ok(());
+            }
+
+            write!(output, "(vm) ")?;
+            output.flush()?;
+        }
+
+        writeln!(output)
+    }
+}
+
+
+pub fn run(program: Program, controller: FunctionController, input: impl BufRead, output: &mut impl Write) -> i32 {
+    let mut shell = Shell {
+        program,
+        debugger: Debugger::new(controller)
+    };
+
+    match shell.session(input, output) {
+        Ok(()) => cli::EXIT_OK,
+        Err(error) => {
+            eprintln!("{}", error);
+            cli::EXIT_LOAD_ERROR
+        }
+    }
+}
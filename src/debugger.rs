@@ -0,0 +1,243 @@
+use std::collections::BTreeSet;
+
+use crate::ptr::Ptr;
+use crate::value::Value;
+use crate::instruction::Instruction;
+use crate::stack_op::StackOp;
+use crate::function::FunctionController;
+use crate::error::VmError;
+
+
+#[derive(Debug)]
+pub enum StopReason {
+    Step,
+    Breakpoint(usize, usize),
+    Watchpoint(Ptr),
+    Finished,
+    Error(Box<VmError>)
+}
+
+
+pub struct Debugger {
+    controller: FunctionController,
+    breakpoints: BTreeSet<(usize, usize)>,
+    watchpoints: Vec<Ptr>
+}
+
+
+impl Debugger {
+    pub fn new(controller: FunctionController) -> Debugger {
+        Debugger {
+            controller,
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec![]
+        }
+    }
+
+    pub fn controller(&self) -> &FunctionController {
+        &self.controller
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &(usize, usize)> {
+        self.breakpoints.iter()
+    }
+
+    // False when the breakpoint was already set.
+    pub fn add_breakpoint(&mut self, function: usize, instruction: usize) -> Result<bool, String> {
+        let length = self.controller.functions().get(&function)
+            .map(|function| function.instructions.len())
+            .ok_or_else(|| format!("No function with id {}", function))?;
+
+        if instruction >= length {
+            return Err(format!("Function {} has {} instruction(s), there is no instruction {}", function, length, instruction));
+        }
+
+        Ok(self.breakpoints.insert((function, instruction)))
+    }
+
+    pub fn remove_breakpoint(&mut self, function: usize, instruction: usize) -> bool {
+        self.breakpoints.remove(&(function, instruction))
+    }
+
+    pub fn watch(&mut self, ptr: Ptr) {
+        if !self.watchpoints.iter().any(|watched| watched.address() == ptr.address()) {
+            self.watchpoints.push(ptr);
+        }
+    }
+
+    pub fn unwatch(&mut self, ptr: &Ptr) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watched| watched.address() != ptr.address());
+
+        count != self.watchpoints.len()
+    }
+
+    // The (function, instruction) the next step will run.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        self.controller.call_chain().last().map(|context| (context.current_fn, context.current_instruction))
+    }
+
+    fn watched_write(&self) -> Option<Ptr> {
+        match self.controller.next_instruction() {
+            Some(Instruction::Stack(StackOp::Pop(ptr))) => {
+                self.watchpoints.iter().find(|watched| watched.address() == ptr.address()).cloned()
+            },
+            // `store` writes through the ptr just below the value on top.
+            Some(Instruction::Stack(StackOp::Store)) => {
+                let current_stack = self.controller.stack().current();
+                let current_stack = current_stack.borrow();
+
+                match current_stack.len().checked_sub(2).map(|index| &current_stack[index]) {
+                    Some(Value::Ptr(ptr)) => self.watchpoints.iter().find(|watched| watched.address() == ptr.address()).cloned(),
+                    _ => None
+                }
+            },
+            _ => None
+        }
+    }
+
+    // Steps until `done` holds for the call depth, stopping early on breakpoints,
+    // watchpoints, errors or the end of the program.
+    fn run_until(&mut self, done: impl Fn(usize) -> bool) -> StopReason {
+        loop {
+            let watched = self.watched_write();
+
+            let running = match self.controller.step() {
+                Ok(running) => running,
+                Err(error) => return StopReason::Error(Box::new(error))
+            };
+
+            if let Some(ptr) = watched {
+                return StopReason::Watchpoint(ptr);
+            }
+
+            if !running {
+                return StopReason::Finished;
+            }
+
+            if done(self.controller.depth()) {
+                return StopReason::Step;
+            }
+
+            if let Some(position) = self.position() {
+                if self.breakpoints.contains(&position) {
+                    return StopReason::Breakpoint(position.0, position.1);
+                }
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> StopReason {
+        self.run_until(|_| true)
+    }
+
+    pub fn step_over(&mut self) -> StopReason {
+        let depth = self.controller.depth();
+
+        self.run_until(|current| current <= depth)
+    }
+
+    pub fn step_out(&mut self) -> StopReason {
+        let depth = self.controller.depth();
+
+        self.run_until(|current| current < depth)
+    }
+
+    pub fn resume(&mut self) -> StopReason {
+        self.run_until(|_| false)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::testing::controller;
+
+    const SOURCE: &str = "
+        ptr x = 0i32
+
+        fn main params=0 returns=0
+            push 1i32
+            call inner
+            pop @x
+            pushptr @x
+            push 5i32
+            store
+            drop
+        end
+
+        fn inner params=0 returns=1
+            push 2i32
+            push 3i32
+            swap
+        end
+    ";
+
+    fn debugger() -> Debugger {
+        Debugger::new(controller(SOURCE))
+    }
+
+    #[test]
+    fn steps_go_into_over_and_out_of_calls() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.position(), Some((1, 0)));
+
+        assert!(matches!(debugger.step(), StopReason::Step));
+        assert_eq!(debugger.position(), Some((1, 1)));
+
+        assert!(matches!(debugger.step(), StopReason::Step));
+        assert_eq!(debugger.position(), Some((2, 0)));
+
+        assert!(matches!(debugger.step_out(), StopReason::Step));
+        assert_eq!(debugger.position(), Some((1, 2)));
+
+        let mut debugger = self::debugger();
+        debugger.step();
+
+        assert!(matches!(debugger.step_over(), StopReason::Step));
+        assert_eq!(debugger.position(), Some((1, 2)));
+    }
+
+    #[test]
+    fn breakpoints_stop_before_their_instruction() {
+        let mut debugger = debugger();
+
+        assert_eq!(debugger.add_breakpoint(2, 2), Ok(true));
+        assert_eq!(debugger.add_breakpoint(2, 2), Ok(false));
+        assert!(debugger.add_breakpoint(2, 3).is_err());
+        assert!(debugger.add_breakpoint(3, 0).is_err());
+        assert_eq!(debugger.breakpoints().count(), 1);
+
+        debugger.step();
+        assert!(matches!(debugger.step_over(), StopReason::Breakpoint(2, 2)));
+
+        assert!(debugger.remove_breakpoint(2, 2));
+        assert!(matches!(debugger.resume(), StopReason::Finished));
+    }
+
+    #[test]
+    fn watchpoints_stop_after_pop_and_store() {
+        let mut debugger = debugger();
+
+        let x = match &debugger.controller().functions()[&1].instructions[2] {
+            Instruction::Stack(StackOp::Pop(ptr)) => ptr.clone(),
+            other => panic!("expected a pop, found {:?}", other)
+        };
+        debugger.watch(x.clone());
+
+        // `store` finds the ptr two below the top, under the value it writes.
+        for (position, value) in [((1, 3), "2i32"), ((1, 6), "5i32")] {
+            match debugger.resume() {
+                StopReason::Watchpoint(ptr) => assert_eq!(ptr.address(), x.address()),
+                other => panic!("expected a watchpoint, found {:?}", other)
+            }
+
+            assert_eq!(debugger.position(), Some(position));
+            assert_eq!(x.value.borrow().to_string(), value);
+        }
+
+        assert!(debugger.unwatch(&x));
+        assert!(matches!(debugger.resume(), StopReason::Finished));
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::numeric::Numeric;
+use crate::value::{Value, ValueType};
+use crate::ptr::Ptr;
+use crate::data_type::{DataType, Typed};
+use crate::instruction::Instruction;
+use crate::stack_op::StackOp;
+use crate::math_op::MathOp;
+use crate::type_op::TypeOp;
+use crate::str_op::StrOp;
+use crate::logic_op::LogicOp;
+use crate::array_op::ArrayOp;
+use crate::map_op::MapOp;
+use crate::struct_op::StructOp;
+use crate::struct_def::StructDef;
+use crate::control_op::ControlOp;
+use crate::function::Function;
+use crate::program::{Program, collect_ptrs};
+
+
+struct Disassembler {
+    function_names: HashMap<usize, String>,
+    ptr_names: HashMap<usize, String>,
+    ptrs: Vec<Ptr>,
+    exports: HashSet<String>
+}
+
+
+impl Disassembler {
+    fn new(functions: &HashMap<usize, Function>, labels: &HashMap<String, usize>, named_ptrs: &[(String, Ptr)], exports: &HashSet<String>) -> Disassembler {
+        let mut disassembler = Disassembler {
+            function_names: HashMap::new(),
+            ptr_names: HashMap::new(),
+            ptrs: vec![],
+            exports: exports.clone()
+        };
+
+        let mut ids: Vec<usize> = functions.keys().copied().collect();
+        ids.sort();
+
+        let mut taken: HashSet<String> = labels.keys().cloned().collect();
+
+        for id in &ids {
+            let name = labels.iter()
+                .find(|(_, label_id)| *label_id == id)
+                .map(|(name, _)| name.clone())
+                .unwrap_or_else(|| unique_name(&format!("fn_{}", id), &mut taken));
+
+            disassembler.function_names.insert(*id, name);
+        }
+
+        let mut taken: HashSet<String> = named_ptrs.iter().map(|(name, _)| name.clone()).collect();
+        let mut anonymous = 0;
+
+        for ptr in collect_ptrs(functions, named_ptrs) {
+            let name = named_ptrs.iter()
+                .find(|(_, named)| named.address() == ptr.address())
+                .map(|(name, _)| name.clone())
+                .unwrap_or_else(|| {
+                    anonymous += 1;
+                    unique_name(&format!("p{}", anonymous - 1), &mut taken)
+                });
+
+            disassembler.ptr_names.insert(ptr.address(), name);
+            disassembler.ptrs.push(ptr);
+        }
+
+        disassembler
+    }
+
+    fn ptr(&self, ptr: &Ptr) -> String {
+        format!("@{}", self.ptr_names[&ptr.address()])
+    }
+
+    fn value(&self, value: &Value) -> String {
+        match value {
+            Value::Str(string) => format!("{:?}", string),
+            Value::Numeric(numeric) => numeric.to_string(),
+            Value::Bool(boolean) => boolean.to_string(),
+            Value::Ptr(ptr) => self.ptr(ptr),
+            Value::Type(data_type) => format!("#{}", data_type),
+            // There are no array, map or struct literals, so these are only readable, see
+            // `unwritable_ptr`.
+            Value::Array(_) | Value::Map(_) | Value::Struct(_) => value.to_string()
+        }
+    }
+
+    fn value_type(&self, value: &ValueType) -> String {
+        match value {
+            ValueType::Ptr(ptr) => format!("*{}", self.ptr_names[&ptr.address()]),
+            ValueType::Value(value) => self.value(value),
+            ValueType::StackValue => "stack".to_string(),
+            ValueType::Local(index) => format!("${}", index)
+        }
+    }
+
+    fn function_operand(&self, value: &ValueType) -> String {
+        if let ValueType::Value(Value::Numeric(Numeric::USize(id))) = value {
+            if let Some(name) = self.function_names.get(id) {
+                return name.clone();
+            }
+        }
+
+        self.value_type(value)
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> String {
+        match instruction {
+            Instruction::Stack(op) => match op {
+                StackOp::Swap            => "swap".to_string(),
+                StackOp::Duplicate       => "dup".to_string(),
+                StackOp::Drop            => "drop".to_string(),
+                StackOp::Pop(ptr)        => format!("pop {}", self.ptr(ptr)),
+                StackOp::Push(value)     => format!("push {}", self.value_type(value)),
+                StackOp::PushPtr(ptr)    => format!("pushptr {}", self.ptr(ptr)),
+                StackOp::DeRef           => "deref".to_string(),
+                StackOp::SubStack(value) => format!("substack {}", self.value_type(value)),
+                StackOp::Destack(value)  => format!("destack {}", self.value_type(value)),
+                StackOp::Len             => "len".to_string(),
+                StackOp::Inspect         => "inspect".to_string(),
+                StackOp::Alloc           => "alloc".to_string(),
+                StackOp::Store           => "store".to_string(),
+                StackOp::StoreLocal(index) => format!("storelocal ${}", index),
+            },
+            Instruction::Math(op) => match op {
+                MathOp::Add           => "add".to_string(),
+                MathOp::Sub           => "sub".to_string(),
+                MathOp::Mul           => "mul".to_string(),
+                MathOp::Div           => "div".to_string(),
+                MathOp::GreaterThan   => "gt".to_string(),
+                MathOp::LessThan      => "lt".to_string(),
+                MathOp::GreaterThanEq => "gte".to_string(),
+                MathOp::LessThanEq    => "lte".to_string(),
+                MathOp::Eql           => "eq".to_string(),
+                MathOp::Rem           => "rem".to_string(),
+                MathOp::Neg           => "neg".to_string(),
+                MathOp::Abs           => "abs".to_string(),
+                MathOp::BitAnd        => "band".to_string(),
+                MathOp::BitOr         => "bor".to_string(),
+                MathOp::BitXor        => "bxor".to_string(),
+                MathOp::Not           => "bnot".to_string(),
+                MathOp::Shl           => "shl".to_string(),
+                MathOp::Shr           => "shr".to_string(),
+                MathOp::RotateLeft    => "rotl".to_string(),
+                MathOp::RotateRight   => "rotr".to_string(),
+                MathOp::Min           => "min".to_string(),
+                MathOp::Max           => "max".to_string(),
+                MathOp::Pow           => "pow".to_string(),
+                MathOp::WrappingAdd   => "add.wrap".to_string(),
+                MathOp::WrappingSub   => "sub.wrap".to_string(),
+                MathOp::WrappingMul   => "mul.wrap".to_string(),
+                MathOp::WrappingDiv   => "div.wrap".to_string(),
+                MathOp::WrappingRem   => "rem.wrap".to_string(),
+                MathOp::WrappingNeg   => "neg.wrap".to_string(),
+                MathOp::WrappingAbs   => "abs.wrap".to_string(),
+                MathOp::WrappingPow   => "pow.wrap".to_string(),
+                MathOp::SaturatingAdd => "add.sat".to_string(),
+                MathOp::SaturatingSub => "sub.sat".to_string(),
+                MathOp::SaturatingMul => "mul.sat".to_string(),
+                MathOp::SaturatingDiv => "div.sat".to_string(),
+                MathOp::SaturatingRem => "rem.sat".to_string(),
+                MathOp::SaturatingNeg => "neg.sat".to_string(),
+                MathOp::SaturatingAbs => "abs.sat".to_string(),
+                MathOp::SaturatingPow => "pow.sat".to_string(),
+            },
+            Instruction::Type(op) => match op {
+                TypeOp::NumericCast(to) => format!("cast {}", to.suffix()),
+                TypeOp::CheckedCast(to) => format!("cast.checked {}", to.suffix()),
+                TypeOp::SaturatingCast(to) => format!("cast.sat {}", to.suffix()),
+                TypeOp::TypeOf => "typeof".to_string(),
+                TypeOp::Is(data_type) => format!("is {}", data_type),
+            },
+            Instruction::Str(op) => match op {
+                StrOp::Concat        => "str.concat".to_string(),
+                StrOp::Len           => "str.len".to_string(),
+                StrOp::CharLen       => "str.chars".to_string(),
+                StrOp::Substring     => "str.sub".to_string(),
+                StrOp::IndexOf       => "str.find".to_string(),
+                StrOp::Split         => "str.split".to_string(),
+                StrOp::Join          => "str.join".to_string(),
+                StrOp::Trim          => "str.trim".to_string(),
+                StrOp::Upper         => "str.upper".to_string(),
+                StrOp::Lower         => "str.lower".to_string(),
+                StrOp::StartsWith    => "str.starts".to_string(),
+                StrOp::EndsWith      => "str.ends".to_string(),
+                StrOp::Eql           => "str.eq".to_string(),
+                StrOp::GreaterThan   => "str.gt".to_string(),
+                StrOp::LessThan      => "str.lt".to_string(),
+                StrOp::GreaterThanEq => "str.gte".to_string(),
+                StrOp::LessThanEq    => "str.lte".to_string(),
+                StrOp::Format        => "str.format".to_string(),
+                StrOp::Parse(to)     => format!("str.parse {}", to.suffix()),
+            },
+            Instruction::Logic(op) => match op {
+                LogicOp::And => "and".to_string(),
+                LogicOp::Or  => "or".to_string(),
+                LogicOp::Xor => "xor".to_string(),
+                LogicOp::Not => "not".to_string(),
+                LogicOp::Eq  => "equal".to_string(),
+                LogicOp::Ne  => "unequal".to_string(),
+            },
+            Instruction::Array(op) => match op {
+                ArrayOp::New    => "arr.new".to_string(),
+                ArrayOp::Push   => "arr.push".to_string(),
+                ArrayOp::Pop    => "arr.pop".to_string(),
+                ArrayOp::Get    => "arr.get".to_string(),
+                ArrayOp::Set    => "arr.set".to_string(),
+                ArrayOp::Len    => "arr.len".to_string(),
+                ArrayOp::Slice  => "arr.slice".to_string(),
+                ArrayOp::Pack   => "arr.pack".to_string(),
+                ArrayOp::Unpack => "arr.unpack".to_string(),
+            },
+            Instruction::Map(op) => match op {
+                MapOp::New      => "map.new".to_string(),
+                MapOp::Insert   => "map.insert".to_string(),
+                MapOp::Get      => "map.get".to_string(),
+                MapOp::GetOr    => "map.getor".to_string(),
+                MapOp::Remove   => "map.remove".to_string(),
+                MapOp::Contains => "map.has".to_string(),
+                MapOp::Len      => "map.len".to_string(),
+                MapOp::Keys     => "map.keys".to_string(),
+                MapOp::Values   => "map.values".to_string(),
+            },
+            Instruction::Struct(op) => match op {
+                StructOp::New(name)  => format!("struct.new {}", name),
+                StructOp::Get(field) => format!("struct.get {}", field),
+                StructOp::Set(field) => format!("struct.set {}", field),
+            },
+            Instruction::Control(op) => match op {
+                ControlOp::Call(function) => format!("call {}", self.function_operand(function)),
+                ControlOp::CallIf(function, predicate) => {
+                    format!("callif {}, {}", self.function_operand(function), self.value_type(predicate))
+                },
+                ControlOp::CallElse(function, predicate) => {
+                    format!("callelse {}, {}", self.function_operand(function), self.value_type(predicate))
+                },
+                ControlOp::TailCall(function) => format!("tailcall {}", self.function_operand(function)),
+                ControlOp::Return => "ret".to_string(),
+                ControlOp::ReturnIf(predicate) => format!("retif {}", self.value_type(predicate)),
+                ControlOp::Jump(target) => format!("jmp {}", target),
+                ControlOp::JumpIf(target, predicate) => format!("jmpif {}, {}", target, self.value_type(predicate)),
+                ControlOp::JumpElse(target, predicate) => format!("jmpelse {}, {}", target, self.value_type(predicate)),
+            }
+        }
+    }
+
+    fn function(&self, id: usize, function: &Function, output: &mut String) {
+        output.push_str(&format!(
+            "fn {} id={} params={} returns={}",
+            self.function_names[&id], id, function.param_count, function.return_count
+        ));
+
+        if !function.ptr_recipie.is_empty() {
+            let recipe: Vec<String> = function.ptr_recipie.iter().map(|value| self.value(value)).collect();
+            output.push_str(&format!(" recipe=[{}]", recipe.join(", ")));
+        }
+
+        output.push('\n');
+
+        let lines: Vec<String> = function.instructions.iter().map(|instruction| self.instruction(instruction)).collect();
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+
+        for (index, line) in lines.iter().enumerate() {
+            output.push_str(&format!("    {:width$}  ; {}\n", line, index, width = width));
+        }
+
+        output.push_str("end\n");
+    }
+
+    fn disassemble(&self, functions: &HashMap<usize, Function>, structs: &HashMap<String, Rc<StructDef>>) -> String {
+        let mut output = String::new();
+
+        let mut names: Vec<&String> = structs.keys().collect();
+        names.sort();
+
+        for name in names {
+            output.push_str(&format!("struct {}\n", structs[name]));
+        }
+
+        for ptr in &self.ptrs {
+            let name = &self.ptr_names[&ptr.address()];
+
+            if self.exports.contains(name) {
+                output.push_str("export ");
+            }
+
+            output.push_str(&format!("ptr {} = {}\n", name, self.value(&ptr.value.borrow())));
+        }
+
+        let mut ids: Vec<&usize> = functions.keys().collect();
+        ids.sort();
+
+        for id in ids {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+
+            self.function(*id, &functions[id], &mut output);
+        }
+
+        output
+    }
+}
+
+
+fn unique_name(base: &str, taken: &mut HashSet<String>) -> String {
+    let mut name = base.to_string();
+
+    while taken.contains(&name) {
+        name.push('_');
+    }
+
+    taken.insert(name.clone());
+
+    name
+}
+
+
+pub fn disassemble(program: &Program) -> String {
+    Disassembler::new(&program.functions, &program.labels, &program.ptrs, &program.exports).disassemble(&program.functions, &program.structs)
+}
+
+
+// The first ptr, by its disassembled name, holding an array, map or struct. Those are only built
+// at runtime and have no literals, so a program holding one can't be saved as assembly.
+pub fn unwritable_ptr(program: &Program) -> Option<(String, DataType)> {
+    let disassembler = Disassembler::new(&program.functions, &program.labels, &program.ptrs, &program.exports);
+
+    disassembler.ptrs.iter().find_map(|ptr| match &*ptr.value.borrow() {
+        value @ (Value::Array(_) | Value::Map(_) | Value::Struct(_)) => {
+            Some((disassembler.ptr_names[&ptr.address()].clone(), value.get_type()))
+        },
+        _ => None
+    })
+}
+
+
+pub fn disassemble_functions(functions: &HashMap<usize, Function>) -> String {
+    Disassembler::new(functions, &HashMap::new(), &[], &HashSet::new()).disassemble(functions, &HashMap::new())
+}
+
+
+// Renders the instructions of one function, naming functions and ptrs the way `disassemble` would.
+pub fn function_lines(functions: &HashMap<usize, Function>, labels: &HashMap<String, usize>, ptrs: &[(String, Ptr)], id: usize) -> Vec<String> {
+    let disassembler = Disassembler::new(functions, labels, ptrs, &HashSet::new());
+
+    functions.get(&id)
+        .map(|function| function.instructions.iter().map(|instruction| disassembler.instruction(instruction)).collect())
+        .unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn disassembly_assembles_back_into_the_same_program() {
+        let shared = Ptr::new(Value::Numeric(Numeric::Int32(5)));
+        let named = Ptr::new(Value::Str("done".to_string()));
+
+        let mut functions = HashMap::new();
+        functions.insert(1, Function {
+            ptr_recipie: vec![Value::Numeric(Numeric::UInt8(3))],
+            instructions: vec![
+                Instruction::Stack(StackOp::PushPtr(shared.clone())),
+                Instruction::Stack(StackOp::Push(ValueType::Ptr(shared.clone()))),
+                Instruction::Stack(StackOp::Pop(shared)),
+                Instruction::Stack(StackOp::PushPtr(named.clone())),
+                Instruction::Control(ControlOp::Call(ValueType::Value(Value::Numeric(Numeric::USize(2)))))
+            ],
+            param_count: 0,
+            return_count: 0
+        });
+        functions.insert(2, Function { ptr_recipie: vec![], instructions: vec![], param_count: 0, return_count: 0 });
+
+        let mut program = Program::new(functions);
+        program.ptrs.push(("message".to_string(), named));
+        program.exports.insert("message".to_string());
+
+        let text = disassemble(&program);
+        let reassembled = assemble(&text).unwrap();
+
+        assert_eq!(disassemble(&reassembled), text);
+
+        // The unnamed ptr gets one name, so its uses still share a ptr once reassembled.
+        let addresses: Vec<usize> = reassembled.functions[&1].instructions.iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Stack(StackOp::PushPtr(ptr) | StackOp::Push(ValueType::Ptr(ptr)) | StackOp::Pop(ptr)) => Some(ptr.address()),
+                _ => None
+            })
+            .collect();
+
+        assert_eq!(addresses.len(), 4);
+        assert!(addresses[..3].iter().all(|address| *address == addresses[0]));
+        assert_ne!(addresses[3], addresses[0]);
+        assert_eq!(reassembled.ptrs.iter().find(|(name, _)| name == "message").unwrap().1.address(), addresses[3]);
+    }
+}
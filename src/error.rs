@@ -0,0 +1,50 @@
+use std::fmt;
+
+use crate::value::Value;
+use crate::instruction::Instruction;
+use crate::stack::format_values;
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorKind {
+    StackUnderflow,
+    TypeMismatch,
+    InvalidFunctionAddress,
+    InvalidJumpTarget,
+    DivisionByZero,
+    Overflow,
+    OutOfRange,
+    InvalidNumber,
+    MissingKey,
+    InvalidStructType,
+    InvalidField,
+}
+
+
+#[derive(Debug)]
+pub struct VmError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub function: usize,
+    pub instruction_index: usize,
+    pub instruction: Option<Box<Instruction>>,
+    pub stack: Vec<Vec<Value>>
+}
+
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: '{}'\nOccoured at instruction {}", self.kind, self.message, self.instruction_index)?;
+
+        if let Some(instruction) = &self.instruction {
+            write!(f, ", {:?}", instruction)?;
+        }
+
+        let stack: Vec<String> = self.stack.iter().map(|values| format_values(values)).collect();
+
+        write!(f, ", fn {}\nWith stack [{}]", self.function, stack.join(", "))
+    }
+}
+
+
+impl std::error::Error for VmError {}
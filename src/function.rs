@@ -6,10 +6,17 @@ use crate::value::Value;
 use crate::stack::Stack;
 use crate::instruction::{Instruction, Runnable, InstructionResult};
 use crate::control::InstructionControl;
+use crate::error::{ErrorKind, VmError};
+use crate::numeric::Promotion;
+use crate::tracer::Tracer;
+use crate::struct_def::StructDef;
+use crate::heap::{Heap, HeapStats};
+use crate::ptr::Ptr;
 
 
 #[derive(Debug)]
 pub struct Function {
+    // The initial values of the function's locals, copied into fresh ptrs on every call.
     pub ptr_recipie: Vec<Value>,
     pub instructions: Vec<Instruction>,
     pub param_count: usize,
@@ -18,9 +25,14 @@ pub struct Function {
 
 
 #[derive(Debug, Clone)]
-struct RuntimeContext {
-    current_fn: usize,
-    current_instruction: usize,
+pub struct RuntimeContext {
+    pub current_fn: usize,
+    pub current_instruction: usize,
+    // Set when a tail call replaces the function, so the frame still returns as many values
+    // as the function it was called as.
+    pub return_count: Option<usize>,
+    // This call's own copies of `ptr_recipie`, read as `$N` and written by `storelocal`.
+    pub locals: Vec<Ptr>
 }
 
 
@@ -29,74 +41,630 @@ impl RuntimeContext {
         RuntimeContext {
             current_fn,
             current_instruction: 0,
+            return_count: None,
+            locals: vec![]
         }
     }
 }
 
 
+// Fresh locals for a call to `id`. They are allocated on the heap since a local can end up
+// holding a ptr to itself.
+fn locals(functions: &HashMap<usize, Function>, heap: &mut Heap, id: usize) -> Vec<Ptr> {
+    functions.get(&id)
+        .map(|function| function.ptr_recipie.iter().map(|value| heap.alloc(value.clone())).collect())
+        .unwrap_or_default()
+}
+
+
 pub struct FunctionController {
     functions: HashMap<usize, Function>,
+    structs: HashMap<String, Rc<StructDef>>,
     context: Vec<Rc<RefCell<RuntimeContext>>>,
-    stack: Stack
+    stack: Stack,
+    heap: Heap,
+    // Ptrs the host holds on to, which keep what they point at alive through collections.
+    roots: Vec<Ptr>,
+    tracer: Option<Box<dyn Tracer>>,
+    promotion: Promotion
 }
 
 
 impl FunctionController {
     pub fn new(functions: HashMap<usize, Function>, start: usize) -> FunctionController {
+        let mut controller = FunctionController::resume(functions, Stack::new(), Heap::new());
+        controller.enter(start);
+
+        controller
+    }
+
+    pub fn resume(functions: HashMap<usize, Function>, stack: Stack, heap: Heap) -> FunctionController {
         FunctionController {
             functions,
-            context: vec![Rc::new(RefCell::new(RuntimeContext::new(start)))],
-            stack: Stack::new()
+            structs: HashMap::new(),
+            context: vec![],
+            stack,
+            heap,
+            roots: vec![],
+            tracer: None,
+            promotion: Promotion::Strict
+        }
+    }
+
+    fn enter(&mut self, id: usize) {
+        let mut context = RuntimeContext::new(id);
+        context.locals = locals(&self.functions, &mut self.heap, id);
+
+        self.context.push(Rc::new(RefCell::new(context)));
+    }
+
+    pub fn into_parts(self) -> (HashMap<usize, Function>, Stack, Heap) {
+        (self.functions, self.stack, self.heap)
+    }
+
+    // Runs `id` to completion on top of the current stack. On error the call chain is
+    // discarded and the stack is cut back to the depth it had before the call.
+    pub fn call(&mut self, id: usize) -> Result<(), VmError> {
+        let depth = self.stack.depth();
+
+        self.enter(id);
+
+        let result = self.run();
+
+        if result.is_err() {
+            self.context.clear();
+            self.stack.truncate(depth);
         }
+
+        result
+    }
+
+    pub fn push(&mut self, value: Value) {
+        self.stack.current().borrow_mut().push(value);
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
     }
 
-    pub fn run(&mut self) {
-        'runtime_loop: loop {
-            if self.context.len() == 0 {
-                break 'runtime_loop;
+    fn error(&self, kind: ErrorKind, message: &str, context: &RuntimeContext, instruction: Option<&Instruction>) -> VmError {
+        VmError {
+            kind,
+            message: message.to_string(),
+            function: context.current_fn,
+            instruction_index: context.current_instruction,
+            instruction: instruction.cloned().map(Box::new),
+            stack: self.stack.snapshot()
+        }
+    }
+
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    // How math ops treat mixed numeric operands, `Promotion::Strict` unless set.
+    pub fn set_promotion(&mut self, promotion: Promotion) {
+        self.promotion = promotion;
+    }
+
+    pub fn promotion(&self) -> Promotion {
+        self.promotion
+    }
+
+    pub fn functions(&self) -> &HashMap<usize, Function> {
+        &self.functions
+    }
+
+    // The struct types `struct.new` can build, by name.
+    pub fn set_structs(&mut self, structs: HashMap<String, Rc<StructDef>>) {
+        self.structs = structs;
+    }
+
+    pub fn structs(&self) -> &HashMap<String, Rc<StructDef>> {
+        &self.structs
+    }
+
+    pub fn add_root(&mut self, ptr: Ptr) {
+        self.roots.push(ptr);
+    }
+
+    // Empties every allocated cell that can't be reached from the stack, the locals of the
+    // running calls, the functions or the host's roots, returning how many there were.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut roots: Vec<Value> = self.stack.snapshot().into_iter().flatten().collect();
+
+        roots.extend(self.roots.iter().cloned().map(Value::Ptr));
+
+        for context in &self.context {
+            roots.extend(context.borrow().locals.iter().cloned().map(Value::Ptr));
+        }
+
+        for function in self.functions.values() {
+            roots.extend(function.ptr_recipie.iter().cloned());
+            roots.extend(function.instructions.iter().flat_map(|instruction| instruction.ptrs()).cloned().map(Value::Ptr));
+        }
+
+        self.heap.collect(&roots)
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap.stats()
+    }
+
+    pub fn call_chain(&self) -> Vec<RuntimeContext> {
+        self.context.iter().map(|context| context.borrow().clone()).collect()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.context.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.context.is_empty()
+    }
+
+    // The instruction the next step will run.
+    pub fn next_instruction(&self) -> Option<&Instruction> {
+        let context = self.context.last()?.borrow();
+
+        self.functions.get(&context.current_fn)?.instructions.get(context.current_instruction)
+    }
+
+    // Pops every function that has run out of instructions, carrying its return values back.
+    fn settle(&mut self) -> Result<(), VmError> {
+        while let Some(context) = self.context.last().cloned() {
+            let context = context.borrow();
+
+            let current_fn = match self.functions.get(&context.current_fn) {
+                Some(current_fn) => current_fn,
+                None => {
+                    let message = format!("Invalid function address {} used", context.current_fn);
+                    return Err(self.error(ErrorKind::InvalidFunctionAddress, &message, &context, None));
+                }
+            };
+
+            if context.current_instruction != current_fn.instructions.len() {
+                break;
+            }
+
+            if self.context.len() != 1 {
+                let carried = self.stack.destack(context.return_count.unwrap_or(current_fn.return_count));
+
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.call_exit(context.current_fn, carried);
+                }
             }
 
-            let cloned_context = self.context.clone();
+            self.context.pop();
+        }
 
-            let mut current_context = cloned_context[self.context.len() - 1].borrow_mut();
+        Ok(())
+    }
 
-            let current_fn = self.functions.get(&current_context.current_fn)
-                .expect("Invalid function address used");
-            
-            while current_context.current_instruction != current_fn.instructions.len() {
-                let result = current_fn.instructions[current_context.current_instruction].run(&mut self.stack);
+    // Runs a single instruction. Returns false once there is nothing left to run.
+    pub fn step(&mut self) -> Result<bool, VmError> {
+        self.settle()?;
 
-                match result {
-                    InstructionResult::None => {
-                        current_context.current_instruction += 1;
-                    },
-                    InstructionResult::Control(control) => {
-                        match control {
-                            InstructionControl::Call(address) => {
-                                current_context.current_instruction += 1;
+        let current_context = match self.context.last().cloned() {
+            Some(current_context) => current_context,
+            None => return Ok(false)
+        };
 
-                                self.stack.substack(self.functions
-                                                    .get(&address)
-                                                    .expect("Invalid function address used")
-                                                    .param_count);
+        let mut current_context = current_context.borrow_mut();
 
-                                self.context.push(Rc::new(RefCell::new(RuntimeContext::new(address))));
+        let instruction = &self.functions[&current_context.current_fn].instructions[current_context.current_instruction];
 
-                                continue 'runtime_loop;
+        // Traces and errors show the instruction as written, with its locals unresolved.
+        let resolved = if instruction.locals().is_empty() {
+            None
+        } else {
+            match instruction.with_locals(&current_context.locals) {
+                Ok(resolved) => Some(resolved),
+                Err(error) => return Err(self.error(error.kind, &error.message, &current_context, Some(instruction)))
+            }
+        };
+
+        if let Some(tracer) = &mut self.tracer {
+            let top = self.stack.current().borrow().last().cloned();
+            tracer.before_instruction(current_context.current_fn, current_context.current_instruction, instruction, top.as_ref());
+        }
+
+        let result = match resolved.as_ref().unwrap_or(instruction) {
+            Instruction::Math(op) => op.run_promoted(&mut self.stack, self.promotion),
+            Instruction::Struct(op) => op.run_with(&mut self.stack, &self.structs),
+            Instruction::Stack(op) => op.run_with(&mut self.stack, &mut self.heap),
+            instruction => instruction.run(&mut self.stack)
+        };
+
+        if let (Some(tracer), InstructionResult::None | InstructionResult::Control(_)) = (&mut self.tracer, &result) {
+            let top = self.stack.current().borrow().last().cloned();
+            tracer.after_instruction(current_context.current_fn, current_context.current_instruction, instruction, top.as_ref());
+        }
+
+        match result {
+            InstructionResult::None => {
+                current_context.current_instruction += 1;
+            },
+            InstructionResult::Control(control) => {
+                match control {
+                    InstructionControl::Call(address) | InstructionControl::TailCall(address) => {
+                        let (param_count, return_count) = match self.functions.get(&address) {
+                            Some(function) => (function.param_count, function.return_count),
+                            None => {
+                                let message = format!("Invalid function address {} used", address);
+                                return Err(self.error(ErrorKind::InvalidFunctionAddress, &message, &current_context, Some(instruction)));
+                            }
+                        };
+
+                        let current_fn = &self.functions[&current_context.current_fn];
+                        let frame_return_count = current_context.return_count.unwrap_or(current_fn.return_count);
+
+                        // The base frame's stack is the program's result, so even `tailcall` enters a
+                        // new frame there. A plain call in tail position is only turned into a tail
+                        // call when nothing observable changes: a callee returning fewer values would
+                        // let the caller's own values through.
+                        let tail_call = self.context.len() > 1
+                            && (matches!(control, InstructionControl::TailCall(_))
+                                || (current_context.current_instruction + 1 == current_fn.instructions.len()
+                                    && return_count >= frame_return_count));
+
+                        if tail_call {
+                            let carried = self.stack.rebase(param_count);
+
+                            if let Some(tracer) = &mut self.tracer {
+                                tracer.tail_call(address, carried);
                             }
+
+                            current_context.current_fn = address;
+                            current_context.current_instruction = 0;
+                            current_context.return_count = Some(frame_return_count);
+                            current_context.locals = locals(&self.functions, &mut self.heap, address);
+                        } else {
+                            current_context.current_instruction += 1;
+
+                            let carried = self.stack.substack(param_count);
+
+                            if let Some(tracer) = &mut self.tracer {
+                                tracer.call_entry(address, carried);
+                            }
+
+                            self.enter(address);
                         }
                     },
-                    InstructionResult::Error(error) => {
-                        panic!("Error: '{}' \nOccoured at instruction {}, {:?}, fn {}\n With stack {:?}", error.message, current_context.current_instruction, current_fn.instructions[current_context.current_instruction], current_context.current_fn, self.stack)
+                    // Skipping to the end lets `settle` destack the return values as usual.
+                    InstructionControl::Return => {
+                        current_context.current_instruction = self.functions[&current_context.current_fn].instructions.len();
+                    },
+                    // Jumping to one past the last instruction is allowed and returns like `Return`.
+                    InstructionControl::Jump(target) => {
+                        let length = self.functions[&current_context.current_fn].instructions.len();
+
+                        match target.resolve(current_context.current_instruction) {
+                            Some(index) if index <= length => current_context.current_instruction = index,
+                            _ => {
+                                let message = format!("Jump target {} is outside of the function", target);
+                                return Err(self.error(ErrorKind::InvalidJumpTarget, &message, &current_context, Some(instruction)));
+                            }
+                        }
                     }
                 }
+            },
+            InstructionResult::Error(error) => {
+                return Err(self.error(error.kind, &error.message, &current_context, Some(instruction)));
             }
+        }
 
-            if self.context.len() != 1 {
-                self.stack.destack(current_fn.return_count);
-            }
+        drop(current_context);
 
-            self.context.pop();
+        self.settle()?;
+
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        Ok(!self.context.is_empty())
+    }
+
+    pub fn run(&mut self) -> Result<(), VmError> {
+        while self.step()? {}
+
+        Ok(())
+    }
+}
+
+
+// Shared by the controller tests.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use crate::assembler;
+
+    // A controller about to run the `main` function of `source`.
+    pub fn controller(source: &str) -> FunctionController {
+        let mut program = assembler::assemble(source).unwrap_or_else(|error| panic!("{}", error));
+        let main = program.function_id("main").unwrap();
+
+        let mut controller = FunctionController::new(std::mem::take(&mut program.functions), main);
+        controller.set_structs(program.structs);
+
+        controller
+    }
+
+    // Runs `source` to the end, returning the base stack.
+    pub fn run(source: &str) -> Vec<String> {
+        let mut controller = controller(source);
+        controller.run().unwrap_or_else(|error| panic!("{}", error));
+
+        let base = controller.stack().current();
+        let base = base.borrow();
+
+        base.iter().map(|value| value.to_string()).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{controller, run};
+    use crate::error::{ErrorKind, VmError};
+    use crate::instruction::Instruction;
+    use crate::control_op::ControlOp;
+    use crate::stack_op::StackOp;
+    use crate::value::ValueType;
+
+    #[test]
+    fn recursive_calls_keep_their_own_locals() {
+        let source = "
+            fn main params=0 returns=0
+                push 10i64
+                call sumdown
+            end
+
+            fn sumdown params=1 returns=1 recipe=[0i64]
+                storelocal $0
+                push $0
+                push 0i64
+                eq
+                jmpif base, stack
+                push $0
+                push 1i64
+                sub
+                call sumdown
+                swap
+                drop
+                push $0
+                add
+                ret
+            base:
+                push 0i64
+            end
+        ";
+
+        assert_eq!(run(source), ["10i64", "55i64"]);
+    }
+
+    #[test]
+    fn locals_are_fresh_for_every_call() {
+        let source = "
+            fn main params=0 returns=0 recipe=[0i32]
+                push 1i32
+                storelocal $0
+                call inner
+                call inner
+                push $0
+            end
+
+            fn inner params=0 returns=1 recipe=[0i32]
+                push $0
+                push 2i32
+                storelocal $0
+            end
+        ";
+
+        assert_eq!(run(source), ["0i32", "0i32", "1i32"]);
+    }
+
+    #[test]
+    fn tail_calls_get_fresh_locals() {
+        // A stale local would make the second iteration return straight away.
+        let source = "
+            fn main params=0 returns=0
+                push 3i32
+                call count
+            end
+
+            fn count params=1 returns=1 recipe=[false]
+                retif $0
+                push true
+                storelocal $0
+                dup
+                push 0i32
+                eq
+                retif stack
+                push 1i32
+                sub
+                tailcall count
+            end
+        ";
+
+        assert_eq!(run(source), ["3i32", "0i32"]);
+    }
+
+    // Steps `source` to the end, returning the base stack and the deepest call chain and stack
+    // of stacks seen along the way.
+    fn run_measured(source: &str) -> (Vec<String>, usize, usize) {
+        let mut controller = controller(source);
+        let (mut depth, mut stack_depth) = (0, 0);
+
+        while controller.step().unwrap_or_else(|error| panic!("{}", error)) {
+            depth = depth.max(controller.depth());
+            stack_depth = stack_depth.max(controller.stack().depth());
         }
+
+        let base = controller.stack().current().borrow().iter().map(|value| value.to_string()).collect();
+
+        (base, depth, stack_depth)
+    }
+
+    #[test]
+    fn calls_in_tail_position_reuse_the_frame() {
+        let source = "
+            fn main params=0 returns=0
+                push 10000i32
+                call down
+            end
+
+            fn down params=1 returns=1
+                dup
+                push 0i32
+                eq
+                retif stack
+                push 1i32
+                sub
+                call down
+            end
+        ";
+
+        assert_eq!(run_measured(source), (vec!["10000i32".to_string(), "0i32".to_string()], 2, 2));
+    }
+
+    #[test]
+    fn the_base_frame_is_never_rebased() {
+        let source = "
+            fn main params=0 returns=0
+                push 7i32
+                push 1i32
+                call down
+            end
+
+            fn down params=1 returns=1
+                push 1i32
+                sub
+            end
+        ";
+
+        assert_eq!(run(source), ["7i32", "1i32", "0i32"]);
+
+        // Nor by an explicit `tailcall`, which enters a frame like `call` there.
+        let source = "
+            fn main params=0 returns=0
+                push 7i32
+                push 1i32
+                tailcall down
+                push 2i32
+            end
+
+            fn down params=1 returns=1
+                push 1i32
+                sub
+            end
+        ";
+
+        let (base, depth, _) = run_measured(source);
+        assert_eq!(base, ["7i32", "1i32", "0i32", "2i32"]);
+        assert_eq!(depth, 2);
+    }
+
+    #[test]
+    fn callees_returning_fewer_values_are_not_tail_called() {
+        let source = "
+            fn main params=0 returns=0
+                call pair
+            end
+
+            fn pair params=0 returns=2
+                push 1i32
+                push 2i32
+                call single
+            end
+
+            fn single params=0 returns=1
+                push 9i32
+            end
+        ";
+
+        assert_eq!(run_measured(source), (vec!["2i32".to_string(), "9i32".to_string()], 3, 3));
+    }
+
+    fn fail(source: &str) -> VmError {
+        controller(source).run().expect_err("the program should fail")
+    }
+
+    fn stack(error: &VmError) -> Vec<Vec<String>> {
+        error.stack.iter().map(|values| values.iter().map(|value| value.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn errors_say_where_they_happened() {
+        let error = fail("
+            fn main params=0 returns=0
+                push 1i32
+                call inner
+            end
+
+            fn inner params=1 returns=0
+                push 99usize
+                call stack
+            end
+        ");
+
+        assert_eq!(error.kind, ErrorKind::InvalidFunctionAddress);
+        assert_eq!((error.function, error.instruction_index), (2, 1));
+        assert!(matches!(error.instruction.as_deref(), Some(Instruction::Control(ControlOp::Call(ValueType::StackValue)))));
+        assert_eq!(stack(&error), [vec!["1i32"], vec!["1i32", "99usize"]]);
+
+        let error = fail("
+            fn main params=0 returns=0
+                push true
+                jmp +5
+            end
+        ");
+
+        assert_eq!(error.kind, ErrorKind::InvalidJumpTarget);
+        assert_eq!((error.function, error.instruction_index), (1, 1));
+        assert!(matches!(error.instruction.as_deref(), Some(Instruction::Control(ControlOp::Jump(_)))));
+        assert_eq!(stack(&error), [vec!["true"]]);
+
+        let error = fail("
+            fn main params=0 returns=0 recipe=[0i32]
+                push $0
+                push $1
+            end
+        ");
+
+        assert_eq!(error.kind, ErrorKind::OutOfRange);
+        assert_eq!((error.function, error.instruction_index), (1, 1));
+        assert!(matches!(error.instruction.as_deref(), Some(Instruction::Stack(StackOp::Push(ValueType::Local(1))))));
+        assert_eq!(stack(&error), [vec!["0i32"]]);
+    }
+
+    #[test]
+    fn failed_calls_unwind() {
+        let mut controller = controller("
+            fn main params=0 returns=0
+            end
+
+            fn outer params=0 returns=0
+                push 1i32
+                call inner
+            end
+
+            fn inner params=0 returns=0
+                push 99usize
+                call stack
+            end
+        ");
+
+        controller.run().unwrap();
+
+        let error = controller.call(2).expect_err("the call should fail");
+
+        assert_eq!((error.function, error.stack.len()), (3, 2));
+        assert!(controller.is_finished());
+        assert_eq!(controller.stack().depth(), 1);
     }
 }
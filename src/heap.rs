@@ -0,0 +1,158 @@
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::value::Value;
+use crate::ptr::Ptr;
+
+// No collection runs until this many cells are tracked, so short programs never pause.
+const MIN_THRESHOLD: usize = 1024;
+
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    pub allocated: usize,
+    // Cells from `alloc` that are still alive, whether or not they are reachable.
+    pub live: usize,
+    pub collections: usize,
+    // Cells that were only kept alive by cycles, emptied by a collection.
+    pub reclaimed: usize,
+    pub last_pause: Duration,
+    pub max_pause: Duration,
+    pub total_pause: Duration
+}
+
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} allocated, {} live, {} collection(s) reclaiming {}, pauses {:?} last / {:?} max / {:?} total",
+            self.allocated, self.live, self.collections, self.reclaimed, self.last_pause, self.max_pause, self.total_pause
+        )
+    }
+}
+
+
+// Tracks the cells made by `alloc`. Ptrs are still reference counted, which frees everything
+// except cycles, so a collection only has to find the tracked cells that can't be reached from
+// the roots and empty them. That drops the references holding the cycle together and the cells
+// are freed as usual. Ptrs the program didn't allocate, such as declared ptrs, are never touched.
+pub struct Heap {
+    cells: Vec<Weak<RefCell<Value>>>,
+    threshold: usize,
+    stats: HeapStats
+}
+
+
+impl Heap {
+    pub fn new() -> Heap {
+        Heap {
+            cells: vec![],
+            threshold: MIN_THRESHOLD,
+            stats: HeapStats::default()
+        }
+    }
+
+    pub fn alloc(&mut self, value: Value) -> Ptr {
+        let ptr = Ptr::new(value);
+
+        self.cells.push(Rc::downgrade(&ptr.value));
+        self.stats.allocated += 1;
+
+        ptr
+    }
+
+    // True once the tracked cells have doubled since the last collection.
+    pub fn should_collect(&self) -> bool {
+        self.cells.len() >= self.threshold
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            live: self.cells.iter().filter(|cell| cell.strong_count() > 0).count(),
+            ..self.stats
+        }
+    }
+
+    // Marks every cell reachable from `roots` and empties the rest. Anything holding a tracked
+    // ptr must be among the roots, or its cell may be emptied under it. Returns the number of
+    // cells reclaimed.
+    pub fn collect(&mut self, roots: &[Value]) -> usize {
+        let start = Instant::now();
+
+        let mut marked = HashSet::new();
+        let mut pending = vec![];
+
+        for root in roots {
+            root.collect_ptrs(&mut pending);
+        }
+
+        // Ptrs are queued rather than followed, so long chains such as linked lists don't recurse.
+        while let Some(ptr) = pending.pop() {
+            if marked.insert(ptr.address()) {
+                ptr.value.borrow().collect_ptrs(&mut pending);
+            }
+        }
+
+        let mut garbage = vec![];
+
+        self.cells.retain(|cell| match cell.upgrade() {
+            Some(cell) if !marked.contains(&(Rc::as_ptr(&cell) as usize)) => {
+                garbage.push(cell);
+                false
+            },
+            Some(_) => true,
+            None => false
+        });
+
+        for cell in &garbage {
+            cell.replace(Value::Bool(false));
+        }
+
+        let reclaimed = garbage.len();
+        drop(garbage);
+
+        self.threshold = (self.cells.len() * 2).max(MIN_THRESHOLD);
+
+        let pause = start.elapsed();
+
+        self.stats.collections += 1;
+        self.stats.reclaimed += reclaimed;
+        self.stats.last_pause = pause;
+        self.stats.max_pause = self.stats.max_pause.max(pause);
+        self.stats.total_pause += pause;
+
+        reclaimed
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreachable_cycles_are_emptied() {
+        let mut heap = Heap::new();
+
+        let kept = heap.alloc(Value::Bool(true));
+        let cycle = heap.alloc(Value::Bool(true));
+        cycle.value.replace(Value::Ptr(cycle.clone()));
+
+        let ring = heap.alloc(Value::Bool(true));
+        ring.value.replace(Value::Array(Rc::new(vec![Value::Ptr(kept.clone()), Value::Ptr(ring.clone())])));
+
+        let weak = Rc::downgrade(&cycle.value);
+        drop(cycle);
+
+        assert_eq!(heap.collect(&[Value::Ptr(ring.clone())]), 1);
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(ring.value.borrow().to_string().matches("ptr").count(), 2);
+
+        let stats = heap.stats();
+        assert_eq!((stats.allocated, stats.live, stats.collections, stats.reclaimed), (3, 2, 1, 1));
+    }
+}
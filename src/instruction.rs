@@ -2,31 +2,48 @@ use crate::stack::Stack;
 use crate::math_op::MathOp;
 use crate::type_op::TypeOp;
 use crate::stack_op::StackOp;
+use crate::str_op::StrOp;
+use crate::logic_op::LogicOp;
+use crate::array_op::ArrayOp;
+use crate::map_op::MapOp;
+use crate::struct_op::StructOp;
 use crate::control_op::ControlOp;
 use crate::control::InstructionControl;
+use crate::value::{Value, ValueType};
+use crate::ptr::Ptr;
+use crate::error::ErrorKind;
 
+// Ops list their operands bottom to top, as in `a, b -> result`. Operands are only popped once
+// the op succeeds, so a failed op leaves the stack as it was.
 pub trait Runnable {
     fn run(&self, stack: &mut Stack) -> InstructionResult;
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Instruction {
     Math(MathOp),
     Stack(StackOp),
     Type(TypeOp),
+    Str(StrOp),
+    Logic(LogicOp),
+    Array(ArrayOp),
+    Map(MapOp),
+    Struct(StructOp),
     Control(ControlOp)
 }
 
 
 pub struct InstructionError {
+    pub kind: ErrorKind,
     pub message: String
 }
 
 
 impl InstructionError {
-    pub fn new(message: &str) -> InstructionError {
+    pub fn new(kind: ErrorKind, message: &str) -> InstructionError {
         InstructionError {
+            kind,
             message: message.to_string()
         }
     }
@@ -46,7 +63,126 @@ impl Runnable for Instruction {
             Instruction::Math(instr) => instr.run(stack),
             Instruction::Stack(instr) => instr.run(stack),
             Instruction::Type(instr) => instr.run(stack),
+            Instruction::Str(instr) => instr.run(stack),
+            Instruction::Logic(instr) => instr.run(stack),
+            Instruction::Array(instr) => instr.run(stack),
+            Instruction::Map(instr) => instr.run(stack),
+            Instruction::Struct(instr) => instr.run(stack),
             Instruction::Control(instr) => instr.run(stack),
         }
     }
 }
+
+
+impl Instruction {
+    fn operands(&self) -> Vec<&ValueType> {
+        match self {
+            Instruction::Stack(StackOp::Push(value))
+            | Instruction::Stack(StackOp::SubStack(value))
+            | Instruction::Stack(StackOp::Destack(value))
+            | Instruction::Control(ControlOp::Call(value))
+            | Instruction::Control(ControlOp::TailCall(value))
+            | Instruction::Control(ControlOp::ReturnIf(value))
+            | Instruction::Control(ControlOp::JumpIf(_, value))
+            | Instruction::Control(ControlOp::JumpElse(_, value)) => vec![value],
+            Instruction::Control(ControlOp::CallIf(function, predicate))
+            | Instruction::Control(ControlOp::CallElse(function, predicate)) => vec![function, predicate],
+            _ => vec![]
+        }
+    }
+
+    fn operands_mut(&mut self) -> Vec<&mut ValueType> {
+        match self {
+            Instruction::Stack(StackOp::Push(value))
+            | Instruction::Stack(StackOp::SubStack(value))
+            | Instruction::Stack(StackOp::Destack(value))
+            | Instruction::Control(ControlOp::Call(value))
+            | Instruction::Control(ControlOp::TailCall(value))
+            | Instruction::Control(ControlOp::ReturnIf(value))
+            | Instruction::Control(ControlOp::JumpIf(_, value))
+            | Instruction::Control(ControlOp::JumpElse(_, value)) => vec![value],
+            Instruction::Control(ControlOp::CallIf(function, predicate))
+            | Instruction::Control(ControlOp::CallElse(function, predicate)) => vec![function, predicate],
+            _ => vec![]
+        }
+    }
+
+    pub fn ptrs(&self) -> Vec<&Ptr> {
+        if let Instruction::Stack(StackOp::Pop(ptr)) | Instruction::Stack(StackOp::PushPtr(ptr)) = self {
+            return vec![ptr];
+        }
+
+        self.operands().into_iter()
+            .filter_map(|value| match value {
+                ValueType::Ptr(ptr) | ValueType::Value(Value::Ptr(ptr)) => Some(ptr),
+                _ => None
+            })
+            .collect()
+    }
+
+    // The indexes of the locals the instruction reads or writes.
+    pub fn locals(&self) -> Vec<usize> {
+        if let Instruction::Stack(StackOp::StoreLocal(index)) = self {
+            return vec![*index];
+        }
+
+        self.operands().into_iter()
+            .filter_map(|value| match value {
+                ValueType::Local(index) => Some(*index),
+                _ => None
+            })
+            .collect()
+    }
+
+    // The instruction with every local swapped for the ptr holding it in the running call, so
+    // locals read and write like any other ptr.
+    pub fn with_locals(&self, locals: &[Ptr]) -> Result<Instruction, InstructionError> {
+        let local = |index: usize| {
+            locals.get(index).cloned().ok_or_else(|| {
+                InstructionError::new(ErrorKind::OutOfRange, &format!("Local ${} is out of range, the function has {}", index, locals.len()))
+            })
+        };
+
+        if let Instruction::Stack(StackOp::StoreLocal(index)) = self {
+            return Ok(Instruction::Stack(StackOp::Pop(local(*index)?)));
+        }
+
+        let mut instruction = self.clone();
+
+        for operand in instruction.operands_mut() {
+            if let ValueType::Local(index) = operand {
+                *operand = ValueType::Ptr(local(*index)?);
+            }
+        }
+
+        Ok(instruction)
+    }
+}
+
+
+// Shared by the op tests.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    // The outcome of an op that never hands back control.
+    pub fn finished(result: InstructionResult) -> Result<(), InstructionError> {
+        match result {
+            InstructionResult::None => Ok(()),
+            InstructionResult::Error(error) => Err(error),
+            InstructionResult::Control(_) => panic!("op returned control")
+        }
+    }
+
+    pub fn run(stack: &mut Stack, op: impl Runnable) -> Result<(), ErrorKind> {
+        finished(op.run(stack)).map_err(|error| error.kind)
+    }
+
+    pub fn push(stack: &mut Stack, value: Value) {
+        stack.current().borrow_mut().push(value);
+    }
+
+    pub fn contents(stack: &Stack) -> Vec<String> {
+        stack.current().borrow().iter().map(|value| value.to_string()).collect()
+    }
+}
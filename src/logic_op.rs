@@ -0,0 +1,147 @@
+use crate::stack::Stack;
+use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
+use crate::value::Value;
+
+#[derive(Debug, Clone)]
+pub enum LogicOp {
+    And,
+    Or,
+    Xor,
+    Not,
+    // Compare any two values, see `Value::equals`.
+    Eq,
+    Ne
+}
+
+
+fn boolean(value: &Value) -> Result<bool, InstructionError> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type bool"))
+    }
+}
+
+
+// Both operands are checked, even when the first one decides the result.
+fn booleans(operands: &[Value]) -> Result<(bool, bool), InstructionError> {
+    Ok((boolean(&operands[0])?, boolean(&operands[1])?))
+}
+
+
+impl LogicOp {
+    fn evaluate(&self, operands: &[Value]) -> Result<bool, InstructionError> {
+        match self {
+            LogicOp::Not => Ok(!boolean(&operands[0])?),
+            LogicOp::And => booleans(operands).map(|(a, b)| a && b),
+            LogicOp::Or  => booleans(operands).map(|(a, b)| a || b),
+            LogicOp::Xor => booleans(operands).map(|(a, b)| a != b),
+            LogicOp::Eq  => Ok(operands[0].equals(&operands[1])),
+            LogicOp::Ne  => Ok(!operands[0].equals(&operands[1]))
+        }
+    }
+}
+
+
+impl Runnable for LogicOp {
+    fn run(&self, stack: &mut Stack) -> InstructionResult {
+        let current_stack = stack.current();
+
+        let mut current_stack = current_stack.borrow_mut();
+
+        let len = current_stack.len();
+        let arity = if let LogicOp::Not = self { 1 } else { 2 };
+
+        if len < arity {
+            let message = format!("Logic operation needs {} operand(s) on the stack", arity);
+            return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, &message));
+        }
+
+        // Operands are only popped once the operation succeeds.
+        match self.evaluate(&current_stack[len - arity..]) {
+            Ok(result) => {
+                current_stack.truncate(len - arity);
+                current_stack.push(Value::Bool(result));
+
+                InstructionResult::None
+            },
+            Err(error) => InstructionResult::Error(error)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ptr::Ptr;
+    use crate::numeric::Numeric;
+    use crate::instruction::testing::{run, push, contents};
+
+    fn logic(op: LogicOp, operands: Vec<Value>) -> Result<String, ErrorKind> {
+        let mut stack = Stack::new();
+        operands.into_iter().for_each(|value| push(&mut stack, value));
+
+        run(&mut stack, op).map(|_| contents(&stack).join(", "))
+    }
+
+    #[test]
+    fn boolean_ops() {
+        for (a, b) in [(false, false), (false, true), (true, false), (true, true)] {
+            let operands = vec![Value::Bool(a), Value::Bool(b)];
+
+            assert_eq!(logic(LogicOp::And, operands.clone()), Ok((a && b).to_string()));
+            assert_eq!(logic(LogicOp::Or, operands.clone()), Ok((a || b).to_string()));
+            assert_eq!(logic(LogicOp::Xor, operands), Ok((a != b).to_string()));
+        }
+
+        assert_eq!(logic(LogicOp::Not, vec![Value::Bool(false)]), Ok("true".to_string()));
+        assert_eq!(logic(LogicOp::Not, vec![Value::Bool(true), Value::Bool(true)]), Ok("true, false".to_string()));
+        assert_eq!(logic(LogicOp::Not, vec![]), Err(ErrorKind::StackUnderflow));
+        assert_eq!(logic(LogicOp::And, vec![Value::Bool(true)]), Err(ErrorKind::StackUnderflow));
+    }
+
+    #[test]
+    fn boolean_ops_check_both_operands() {
+        let number = Value::Numeric(Numeric::Int32(5));
+
+        // `false` decides `and` and `true` decides `or`, but the other operand is still checked.
+        for (op, decider) in [(LogicOp::And, false), (LogicOp::Or, true), (LogicOp::Xor, true)] {
+            assert_eq!(logic(op.clone(), vec![Value::Bool(decider), number.clone()]), Err(ErrorKind::TypeMismatch));
+            assert_eq!(logic(op, vec![number.clone(), Value::Bool(decider)]), Err(ErrorKind::TypeMismatch));
+        }
+
+        assert_eq!(logic(LogicOp::Not, vec![number]), Err(ErrorKind::TypeMismatch));
+
+        // A failed op leaves its operands.
+        let mut stack = Stack::new();
+        push(&mut stack, Value::Bool(false));
+        push(&mut stack, Value::Str("no".to_string()));
+
+        assert_eq!(run(&mut stack, LogicOp::And), Err(ErrorKind::TypeMismatch));
+        assert_eq!(contents(&stack), ["false", "\"no\""]);
+    }
+
+    #[test]
+    fn equality_of_any_two_values() {
+        let str = |string: &str| Value::Str(string.to_string());
+        let ptr = Ptr::new(Value::Bool(false));
+
+        let cases = [
+            (str("a"), str("a"), true),
+            (str("a"), str("b"), false),
+            (Value::Bool(true), Value::Bool(true), true),
+            (Value::Bool(true), Value::Bool(false), false),
+            (Value::Ptr(ptr.clone()), Value::Ptr(ptr), true),
+            // Ptrs compare by identity, not by what they hold.
+            (Value::Ptr(Ptr::new(Value::Bool(false))), Value::Ptr(Ptr::new(Value::Bool(false))), false),
+            (str("true"), Value::Bool(true), false),
+            (Value::Numeric(Numeric::Int32(1)), Value::Bool(true), false),
+        ];
+
+        for (a, b, equal) in cases {
+            assert_eq!(logic(LogicOp::Eq, vec![a.clone(), b.clone()]), Ok(equal.to_string()), "{} equal {}", a, b);
+            assert_eq!(logic(LogicOp::Ne, vec![a.clone(), b.clone()]), Ok((!equal).to_string()), "{} unequal {}", a, b);
+        }
+    }
+}
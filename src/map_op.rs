@@ -0,0 +1,180 @@
+use std::rc::Rc;
+use std::collections::HashMap;
+
+use crate::stack::Stack;
+use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
+use crate::numeric::Numeric;
+use crate::value::{Value, MapKey, sorted_entries};
+
+// Keys are strs, bools and integers, see `MapKey`. Every op leaves the map where it was and
+// pushes its results above it.
+#[derive(Debug, Clone)]
+pub enum MapOp {
+    // -> an empty map
+    New,
+    // map, key, value -> map, replacing any value already under key
+    Insert,
+    // map, key -> map, value. A missing key is a `MissingKey` error.
+    Get,
+    // map, key, default -> map, the value under key or default
+    GetOr,
+    // map, key -> map, whether key was there
+    Remove,
+    // map, key -> map, bool
+    Contains,
+    // map -> map, entry count
+    Len,
+    // map -> map, an array of the keys sorted as by `sorted_entries`
+    Keys,
+    // map -> map, an array of the values in the same order as `Keys`
+    Values
+}
+
+
+fn map(value: &mut Value) -> Result<&mut Rc<HashMap<MapKey, Value>>, InstructionError> {
+    match value {
+        Value::Map(map) => Ok(map),
+        _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type map"))
+    }
+}
+
+
+impl MapOp {
+    fn arity(&self) -> usize {
+        match self {
+            MapOp::New => 0,
+            MapOp::Len | MapOp::Keys | MapOp::Values => 1,
+            MapOp::Get | MapOp::Remove | MapOp::Contains => 2,
+            MapOp::Insert | MapOp::GetOr => 3
+        }
+    }
+
+    // Everything is checked before the map is changed, so a failed op can put its operands back.
+    fn evaluate(&self, operands: &mut [Value]) -> Result<Vec<Value>, InstructionError> {
+        if let MapOp::New = self {
+            return Ok(vec![Value::Map(Rc::new(HashMap::new()))]);
+        }
+
+        let (head, rest) = operands.split_first_mut().unwrap();
+        let entries = map(head)?;
+        let key = rest.first().map(MapKey::from_value).transpose()?;
+
+        let results = match (self, key) {
+            (MapOp::Insert, Some(key)) => {
+                Rc::make_mut(entries).insert(key, rest[1].clone());
+                vec![]
+            },
+            (MapOp::Get, Some(key)) => match entries.get(&key) {
+                Some(value) => vec![value.clone()],
+                None => return Err(InstructionError::new(ErrorKind::MissingKey, &format!("No entry for {}", rest[0])))
+            },
+            (MapOp::GetOr, Some(key)) => vec![entries.get(&key).unwrap_or(&rest[1]).clone()],
+            (MapOp::Remove, Some(key)) => {
+                // Only copy a shared map when there is something to remove.
+                let present = entries.contains_key(&key) && Rc::make_mut(entries).remove(&key).is_some();
+                vec![Value::Bool(present)]
+            },
+            (MapOp::Contains, Some(key)) => vec![Value::Bool(entries.contains_key(&key))],
+            (MapOp::Len, _) => vec![Value::Numeric(Numeric::USize(entries.len()))],
+            (MapOp::Keys, _) => {
+                let keys = sorted_entries(entries).into_iter().map(|(key, _)| key.to_value()).collect();
+                vec![Value::Array(Rc::new(keys))]
+            },
+            (MapOp::Values, _) => {
+                let values = sorted_entries(entries).into_iter().map(|(_, value)| value.clone()).collect();
+                vec![Value::Array(Rc::new(values))]
+            },
+            _ => unreachable!()
+        };
+
+        Ok([head.clone()].into_iter().chain(results).collect())
+    }
+}
+
+
+impl Runnable for MapOp {
+    fn run(&self, stack: &mut Stack) -> InstructionResult {
+        let current_stack = stack.current();
+
+        let mut current_stack = current_stack.borrow_mut();
+
+        let arity = self.arity();
+
+        if current_stack.len() < arity {
+            let message = format!("Map operation needs {} operand(s) on the stack", arity);
+            return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, &message));
+        }
+
+        // Taking the operands off the stack lets a map that isn't shared be changed in place.
+        let start = current_stack.len() - arity;
+        let mut operands = current_stack.split_off(start);
+
+        match self.evaluate(&mut operands) {
+            Ok(results) => {
+                current_stack.extend(results);
+
+                InstructionResult::None
+            },
+            Err(error) => {
+                current_stack.extend(operands);
+
+                InstructionResult::Error(error)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::testing::{run, push};
+
+    fn pop(stack: &mut Stack) -> String {
+        stack.current().borrow_mut().pop().unwrap().to_string()
+    }
+
+    fn insert(stack: &mut Stack, key: Value, value: Value) {
+        push(stack, key);
+        push(stack, value);
+        run(stack, MapOp::Insert).unwrap();
+    }
+
+    #[test]
+    fn integer_keys_keep_their_type() {
+        let mut stack = Stack::new();
+
+        run(&mut stack, MapOp::New).unwrap();
+        insert(&mut stack, Value::Numeric(Numeric::Int64(1)), Value::Bool(false));
+        insert(&mut stack, Value::Numeric(Numeric::Int32(1)), Value::Bool(true));
+        insert(&mut stack, Value::Str("a".to_string()), Value::Bool(true));
+
+        run(&mut stack, MapOp::Keys).unwrap();
+        assert_eq!(pop(&mut stack), "[\"a\", 1i32, 1i64]");
+
+        push(&mut stack, Value::Numeric(Numeric::Int64(1)));
+        run(&mut stack, MapOp::Get).unwrap();
+        assert_eq!(pop(&mut stack), "false");
+    }
+
+    #[test]
+    fn bad_keys_leave_the_operands() {
+        let mut stack = Stack::new();
+
+        run(&mut stack, MapOp::New).unwrap();
+        push(&mut stack, Value::Numeric(Numeric::Float64(1.0)));
+        push(&mut stack, Value::Bool(true));
+        assert_eq!(run(&mut stack, MapOp::Insert), Err(ErrorKind::TypeMismatch));
+        assert_eq!(stack.current().borrow().len(), 3);
+
+        pop(&mut stack);
+        pop(&mut stack);
+        push(&mut stack, Value::Str("missing".to_string()));
+        assert_eq!(run(&mut stack, MapOp::Get), Err(ErrorKind::MissingKey));
+
+        run(&mut stack, MapOp::Remove).unwrap();
+        assert_eq!(pop(&mut stack), "false");
+        assert_eq!(pop(&mut stack), "{}");
+    }
+}
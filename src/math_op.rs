@@ -1,8 +1,10 @@
 use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
 use crate::value::Value;
+use crate::numeric::{Numeric, Promotion};
 use crate::stack::Stack;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum MathOp {
     Add,
     Sub,
@@ -12,45 +14,475 @@ pub enum MathOp {
     LessThan,
     GreaterThanEq,
     LessThanEq,
-    Eql
+    Eql,
+    Rem,
+    Neg,
+    Abs,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Not,
+    Shl,
+    Shr,
+    RotateLeft,
+    RotateRight,
+    Min,
+    Max,
+    Pow,
+    WrappingAdd,
+    WrappingSub,
+    WrappingMul,
+    WrappingDiv,
+    WrappingRem,
+    WrappingNeg,
+    WrappingAbs,
+    WrappingPow,
+    SaturatingAdd,
+    SaturatingSub,
+    SaturatingMul,
+    SaturatingDiv,
+    SaturatingRem,
+    SaturatingNeg,
+    SaturatingAbs,
+    SaturatingPow
 }
 
+
+impl MathOp {
+    fn is_unary(&self) -> bool {
+        matches!(
+            self,
+            MathOp::Neg | MathOp::Abs | MathOp::Not
+            | MathOp::WrappingNeg | MathOp::WrappingAbs | MathOp::SaturatingNeg | MathOp::SaturatingAbs
+        )
+    }
+
+    // The right operand is a shift amount or an integer exponent rather than a value of the
+    // left operand's type, so it is not promoted.
+    fn takes_amount(&self, a: &Numeric) -> bool {
+        match self {
+            MathOp::Shl | MathOp::Shr | MathOp::RotateLeft | MathOp::RotateRight => true,
+            MathOp::Pow | MathOp::WrappingPow | MathOp::SaturatingPow => !a.is_float(),
+            _ => false
+        }
+    }
+}
+
+// Operands are only popped once the operation succeeds, so errors leave the stack as it was.
 fn push_to_stack(value: Option<Value>, current_stack: &mut Vec<Value>) -> InstructionResult {
     if let Some(value) = value {
+        current_stack.truncate(current_stack.len() - 2);
         current_stack.push(value);
 
         InstructionResult::None
     } else {
-        InstructionResult::Error(InstructionError::new("Oprands not of matching numeric sub-types"))
+        InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Oprands not of matching numeric sub-types"))
     }
 }
 
-impl Runnable for MathOp {
+fn replace_operands(count: usize, result: Result<Numeric, InstructionError>, current_stack: &mut Vec<Value>) -> InstructionResult {
+    match result {
+        Ok(value) => {
+            current_stack.truncate(current_stack.len() - count);
+            current_stack.push(Value::Numeric(value));
 
-    fn run(&self, stack: &mut Stack) -> InstructionResult {
+            InstructionResult::None
+        },
+        Err(error) => InstructionResult::Error(error)
+    }
+}
+
+impl MathOp {
+    // Runs the operation with mixed numeric operands converted as `promotion` describes.
+    pub fn run_promoted(&self, stack: &mut Stack, promotion: Promotion) -> InstructionResult {
         let current_stack = stack.current();
 
         let mut current_stack = current_stack.borrow_mut();
 
-        let b = current_stack.pop().unwrap();
-        let a = current_stack.pop().unwrap();
+        if self.is_unary() {
+            let a = match current_stack.last() {
+                Some(Value::Numeric(a)) => a.clone(),
+                Some(_) => return InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type numeric")),
+                None => return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Unary math operations need an operand on the stack"))
+            };
+
+            let result = match self {
+                MathOp::Neg           => a.neg(),
+                MathOp::Abs           => a.abs(),
+                MathOp::WrappingNeg   => a.wrapping_neg(),
+                MathOp::WrappingAbs   => a.wrapping_abs(),
+                MathOp::SaturatingNeg => a.saturating_neg(),
+                MathOp::SaturatingAbs => a.saturating_abs(),
+                _                     => a.not()
+            };
+
+            return replace_operands(1, result, &mut current_stack);
+        }
+
+        if current_stack.len() < 2 {
+            return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Math operations need two operands on the stack"));
+        }
+
+        let b = current_stack[current_stack.len() - 1].clone();
+        let a = current_stack[current_stack.len() - 2].clone();
 
         if let (Value::Numeric(a), Value::Numeric(b)) = (a, b) {
+            let (a, b) = if self.takes_amount(&a) { (a, b) } else { promotion.promote(a, b) };
+
             match self {
-                MathOp::Add           => push_to_stack(a.add(&b), &mut current_stack),
-                MathOp::Sub           => push_to_stack(a.sub(&b), &mut current_stack),
-                MathOp::Mul           => push_to_stack(a.mul(&b), &mut current_stack),
-                MathOp::Div           => push_to_stack(a.div(&b), &mut current_stack),
+                MathOp::Add           => replace_operands(2, a.add(&b), &mut current_stack),
+                MathOp::Sub           => replace_operands(2, a.sub(&b), &mut current_stack),
+                MathOp::Mul           => replace_operands(2, a.mul(&b), &mut current_stack),
+                MathOp::Div           => replace_operands(2, a.div(&b), &mut current_stack),
                 MathOp::GreaterThan   => push_to_stack(a.greater_than(&b), &mut current_stack),
                 MathOp::LessThan      => push_to_stack(a.less_than(&b), &mut current_stack),
                 MathOp::GreaterThanEq => push_to_stack(a.greater_than_eq(&b), &mut current_stack),
                 MathOp::LessThanEq    => push_to_stack(a.less_than_eq(&b), &mut current_stack),
                 MathOp::Eql           => push_to_stack(a.eq(&b), &mut current_stack),
-                
+                MathOp::Rem           => replace_operands(2, a.rem(&b), &mut current_stack),
+                MathOp::BitAnd        => replace_operands(2, a.bit_and(&b), &mut current_stack),
+                MathOp::BitOr         => replace_operands(2, a.bit_or(&b), &mut current_stack),
+                MathOp::BitXor        => replace_operands(2, a.bit_xor(&b), &mut current_stack),
+                MathOp::Shl           => replace_operands(2, a.shl(&b), &mut current_stack),
+                MathOp::Shr           => replace_operands(2, a.shr(&b), &mut current_stack),
+                MathOp::RotateLeft    => replace_operands(2, a.rotate_left(&b), &mut current_stack),
+                MathOp::RotateRight   => replace_operands(2, a.rotate_right(&b), &mut current_stack),
+                MathOp::Min           => replace_operands(2, a.min(&b), &mut current_stack),
+                MathOp::Max           => replace_operands(2, a.max(&b), &mut current_stack),
+                MathOp::Pow           => replace_operands(2, a.pow(&b), &mut current_stack),
+                MathOp::WrappingAdd   => replace_operands(2, a.wrapping_add(&b), &mut current_stack),
+                MathOp::WrappingSub   => replace_operands(2, a.wrapping_sub(&b), &mut current_stack),
+                MathOp::WrappingMul   => replace_operands(2, a.wrapping_mul(&b), &mut current_stack),
+                MathOp::WrappingDiv   => replace_operands(2, a.wrapping_div(&b), &mut current_stack),
+                MathOp::WrappingRem   => replace_operands(2, a.wrapping_rem(&b), &mut current_stack),
+                MathOp::WrappingPow   => replace_operands(2, a.wrapping_pow(&b), &mut current_stack),
+                MathOp::SaturatingAdd => replace_operands(2, a.saturating_add(&b), &mut current_stack),
+                MathOp::SaturatingSub => replace_operands(2, a.saturating_sub(&b), &mut current_stack),
+                MathOp::SaturatingMul => replace_operands(2, a.saturating_mul(&b), &mut current_stack),
+                MathOp::SaturatingDiv => replace_operands(2, a.saturating_div(&b), &mut current_stack),
+                MathOp::SaturatingRem => replace_operands(2, a.saturating_rem(&b), &mut current_stack),
+                MathOp::SaturatingPow => replace_operands(2, a.saturating_pow(&b), &mut current_stack),
+                MathOp::Neg | MathOp::Abs | MathOp::Not
+                | MathOp::WrappingNeg | MathOp::WrappingAbs | MathOp::SaturatingNeg | MathOp::SaturatingAbs => unreachable!()
             }
         } else {
-            InstructionResult::Error(InstructionError::new("An oprand is not of type numeric"))
+            InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "An oprand is not of type numeric"))
+        }
+
+    }
+}
+
+
+impl Runnable for MathOp {
+    fn run(&self, stack: &mut Stack) -> InstructionResult {
+        self.run_promoted(stack, Promotion::Strict)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numeric::NumericType;
+    use crate::instruction::testing::finished;
+
+    enum Kind {
+        Any,
+        Signed,
+        IntOnly,
+        Compare
+    }
+
+    const TYPES: [NumericType; 14] = [
+        NumericType::UInt8, NumericType::UInt16, NumericType::UInt32, NumericType::UInt64, NumericType::UInt128,
+        NumericType::Int8, NumericType::Int16, NumericType::Int32, NumericType::Int64, NumericType::Int128,
+        NumericType::Float32, NumericType::Float64, NumericType::USize, NumericType::ISize
+    ];
+
+    const INT_TYPES: [NumericType; 12] = [
+        NumericType::UInt8, NumericType::UInt16, NumericType::UInt32, NumericType::UInt64, NumericType::UInt128,
+        NumericType::Int8, NumericType::Int16, NumericType::Int32, NumericType::Int64, NumericType::Int128,
+        NumericType::USize, NumericType::ISize
+    ];
+
+    // Every op must be listed here by hand. `kind` and `cases` fail to compile when an op is
+    // missing from them, but an op missing from this list is silently left out of the tests.
+    fn all_ops() -> Vec<MathOp> {
+        vec![
+            MathOp::Add, MathOp::Sub, MathOp::Mul, MathOp::Div, MathOp::GreaterThan, MathOp::LessThan,
+            MathOp::GreaterThanEq, MathOp::LessThanEq, MathOp::Eql, MathOp::Rem, MathOp::Neg, MathOp::Abs,
+            MathOp::BitAnd, MathOp::BitOr, MathOp::BitXor, MathOp::Not, MathOp::Shl, MathOp::Shr,
+            MathOp::RotateLeft, MathOp::RotateRight, MathOp::Min, MathOp::Max, MathOp::Pow,
+            MathOp::WrappingAdd, MathOp::WrappingSub, MathOp::WrappingMul, MathOp::WrappingDiv, MathOp::WrappingRem,
+            MathOp::WrappingNeg, MathOp::WrappingAbs, MathOp::WrappingPow,
+            MathOp::SaturatingAdd, MathOp::SaturatingSub, MathOp::SaturatingMul, MathOp::SaturatingDiv,
+            MathOp::SaturatingRem, MathOp::SaturatingNeg, MathOp::SaturatingAbs, MathOp::SaturatingPow
+        ]
+    }
+
+    fn kind(op: &MathOp) -> Kind {
+        match op {
+            MathOp::GreaterThan | MathOp::LessThan | MathOp::GreaterThanEq | MathOp::LessThanEq | MathOp::Eql => Kind::Compare,
+            MathOp::Neg | MathOp::WrappingNeg | MathOp::SaturatingNeg => Kind::Signed,
+            MathOp::BitAnd | MathOp::BitOr | MathOp::BitXor | MathOp::Not
+            | MathOp::Shl | MathOp::Shr | MathOp::RotateLeft | MathOp::RotateRight => Kind::IntOnly,
+            MathOp::Add | MathOp::Sub | MathOp::Mul | MathOp::Div | MathOp::Rem | MathOp::Abs
+            | MathOp::Min | MathOp::Max | MathOp::Pow
+            | MathOp::WrappingAdd | MathOp::WrappingSub | MathOp::WrappingMul | MathOp::WrappingDiv
+            | MathOp::WrappingRem | MathOp::WrappingAbs | MathOp::WrappingPow
+            | MathOp::SaturatingAdd | MathOp::SaturatingSub | MathOp::SaturatingMul | MathOp::SaturatingDiv
+            | MathOp::SaturatingRem | MathOp::SaturatingAbs | MathOp::SaturatingPow => Kind::Any
+        }
+    }
+
+    // (a, b, expected) parsed as each type in turn, cases whose operands don't parse as the type
+    // are skipped. No expected value only checks the result type.
+    fn cases(op: &MathOp) -> Vec<(&'static str, Option<&'static str>, Option<&'static str>)> {
+        match op {
+            MathOp::Add | MathOp::WrappingAdd | MathOp::SaturatingAdd => vec![("7", Some("2"), Some("9"))],
+            MathOp::Sub | MathOp::WrappingSub | MathOp::SaturatingSub => vec![("7", Some("2"), Some("5"))],
+            MathOp::Mul | MathOp::WrappingMul | MathOp::SaturatingMul => vec![("7", Some("2"), Some("14"))],
+            MathOp::Div | MathOp::WrappingDiv | MathOp::SaturatingDiv => vec![("8", Some("2"), Some("4"))],
+            MathOp::Rem | MathOp::WrappingRem | MathOp::SaturatingRem => vec![("7", Some("2"), Some("1"))],
+            MathOp::Pow | MathOp::WrappingPow | MathOp::SaturatingPow => vec![("3", Some("2"), Some("9"))],
+            MathOp::Neg | MathOp::WrappingNeg | MathOp::SaturatingNeg => vec![("5", None, Some("-5"))],
+            MathOp::Abs | MathOp::WrappingAbs | MathOp::SaturatingAbs => {
+                vec![("-5", None, Some("5")), ("5", None, Some("5"))]
+            },
+            MathOp::GreaterThan   => vec![("7", Some("2"), Some("true"))],
+            MathOp::LessThan      => vec![("7", Some("2"), Some("false"))],
+            MathOp::GreaterThanEq => vec![("2", Some("2"), Some("true"))],
+            MathOp::LessThanEq    => vec![("7", Some("2"), Some("false"))],
+            MathOp::Eql           => vec![("2", Some("2"), Some("true"))],
+            MathOp::BitAnd        => vec![("12", Some("10"), Some("8"))],
+            MathOp::BitOr         => vec![("12", Some("10"), Some("14"))],
+            MathOp::BitXor        => vec![("12", Some("10"), Some("6"))],
+            MathOp::Not           => vec![("-1", None, Some("0")), ("0", None, None)],
+            MathOp::Shl           => vec![("3", Some("2"), Some("12"))],
+            MathOp::Shr           => vec![("12", Some("2"), Some("3"))],
+            MathOp::RotateLeft    => vec![("3", Some("2"), Some("12"))],
+            MathOp::RotateRight   => vec![("12", Some("2"), Some("3"))],
+            MathOp::Min           => vec![("7", Some("2"), Some("2"))],
+            MathOp::Max           => vec![("7", Some("2"), Some("7"))],
+        }
+    }
+
+    fn bounds(numeric_type: &NumericType) -> (String, String) {
+        match numeric_type {
+            NumericType::UInt8   => (u8::MIN.to_string(), u8::MAX.to_string()),
+            NumericType::UInt16  => (u16::MIN.to_string(), u16::MAX.to_string()),
+            NumericType::UInt32  => (u32::MIN.to_string(), u32::MAX.to_string()),
+            NumericType::UInt64  => (u64::MIN.to_string(), u64::MAX.to_string()),
+            NumericType::UInt128 => (u128::MIN.to_string(), u128::MAX.to_string()),
+            NumericType::Int8    => (i8::MIN.to_string(), i8::MAX.to_string()),
+            NumericType::Int16   => (i16::MIN.to_string(), i16::MAX.to_string()),
+            NumericType::Int32   => (i32::MIN.to_string(), i32::MAX.to_string()),
+            NumericType::Int64   => (i64::MIN.to_string(), i64::MAX.to_string()),
+            NumericType::Int128  => (i128::MIN.to_string(), i128::MAX.to_string()),
+            NumericType::USize   => (usize::MIN.to_string(), usize::MAX.to_string()),
+            NumericType::ISize   => (isize::MIN.to_string(), isize::MAX.to_string()),
+            NumericType::Float32 | NumericType::Float64 => unreachable!()
+        }
+    }
+
+    fn numeric(numeric_type: &NumericType, text: &str) -> Numeric {
+        numeric_type.parse(text).unwrap_or_else(|| panic!("'{}' is not a valid {}", text, numeric_type.suffix()))
+    }
+
+    // Runs `op` on `operands` and returns the new stack, or the error.
+    fn run(op: &MathOp, operands: Vec<Value>) -> Result<Vec<Value>, InstructionError> {
+        let mut stack = Stack::new();
+        stack.current().borrow_mut().extend(operands);
+
+        finished(op.run(&mut stack)).map(|_| stack.current().borrow().clone())
+    }
+
+    fn binary(op: MathOp, a: Numeric, b: Numeric) -> Result<String, InstructionError> {
+        run(&op, vec![Value::Numeric(a), Value::Numeric(b)]).map(|stack| stack[0].to_string())
+    }
+
+    #[test]
+    fn every_op_on_every_numeric_type() {
+        for op in all_ops() {
+            for numeric_type in TYPES.iter() {
+                for (a, b, expected) in cases(&op) {
+                    let (Some(a), Some(b)) = (numeric_type.parse(a), b.map(|b| numeric_type.parse(b))) else {
+                        continue;
+                    };
+
+                    let mut operands = vec![Value::Numeric(a)];
+                    operands.extend(b.map(Value::Numeric));
+
+                    let float = matches!(numeric_type, NumericType::Float32 | NumericType::Float64);
+                    let unsigned = !numeric_type.parse("0").unwrap().is_signed();
+                    let context = format!("{:?} on {}", op, numeric_type.suffix());
+
+                    let result = run(&op, operands);
+
+                    match kind(&op) {
+                        Kind::IntOnly if float => {
+                            assert_eq!(result.err().map(|error| error.kind), Some(ErrorKind::TypeMismatch), "{}", context);
+                            continue;
+                        },
+                        Kind::Signed if unsigned => {
+                            assert_eq!(result.err().map(|error| error.kind), Some(ErrorKind::TypeMismatch), "{}", context);
+                            continue;
+                        },
+                        _ => {}
+                    }
+
+                    let stack = result.unwrap_or_else(|error| panic!("{}: {}", context, error.message));
+                    assert_eq!(stack.len(), 1, "{}", context);
+
+                    match (kind(&op), &stack[0]) {
+                        (Kind::Compare, Value::Bool(_)) => {
+                            if let Some(expected) = expected {
+                                assert_eq!(stack[0].to_string(), expected, "{}", context);
+                            }
+                        },
+                        (Kind::Compare, other) => panic!("{}: expected a bool, found {}", context, other),
+                        (_, Value::Numeric(result)) => {
+                            assert_eq!(result.numeric_type().suffix(), numeric_type.suffix(), "{}", context);
+
+                            if let Some(expected) = expected {
+                                assert_eq!(result.to_string(), numeric(numeric_type, expected).to_string(), "{}", context);
+                            }
+                        },
+                        (_, other) => panic!("{}: expected a numeric, found {}", context, other)
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_numeric_types_are_type_errors() {
+        for a in TYPES.iter() {
+            for b in TYPES.iter().filter(|b| b.suffix() != a.suffix()) {
+                for op in [MathOp::Add, MathOp::Sub, MathOp::Mul, MathOp::Div, MathOp::Min, MathOp::LessThan, MathOp::Eql] {
+                    let result = binary(op.clone(), numeric(a, "2"), numeric(b, "2"));
+
+                    assert_eq!(result.err().map(|error| error.kind), Some(ErrorKind::TypeMismatch), "{:?} {} {}", op, a.suffix(), b.suffix());
+                }
+            }
         }
+    }
+
+    #[test]
+    fn overflow_flavours() {
+        for numeric_type in INT_TYPES.iter() {
+            let (min, max) = bounds(numeric_type);
+            let one = || numeric(numeric_type, "1");
+            let max = || numeric(numeric_type, &max);
+            let min = || numeric(numeric_type, &min);
+
+            assert_eq!(binary(MathOp::Add, max(), one()).err().map(|error| error.kind), Some(ErrorKind::Overflow));
+            assert_eq!(binary(MathOp::WrappingAdd, max(), one()).ok(), Some(min().to_string()));
+            assert_eq!(binary(MathOp::SaturatingAdd, max(), one()).ok(), Some(max().to_string()));
+
+            assert_eq!(binary(MathOp::Sub, min(), one()).err().map(|error| error.kind), Some(ErrorKind::Overflow));
+            assert_eq!(binary(MathOp::WrappingSub, min(), one()).ok(), Some(max().to_string()));
+            assert_eq!(binary(MathOp::SaturatingSub, min(), one()).ok(), Some(min().to_string()));
 
+            let two = numeric(numeric_type, "2");
+            assert_eq!(binary(MathOp::Mul, max(), two.clone()).err().map(|error| error.kind), Some(ErrorKind::Overflow));
+            assert_eq!(binary(MathOp::SaturatingMul, max(), two.clone()).ok(), Some(max().to_string()));
+            assert_eq!(binary(MathOp::Pow, max(), two.clone()).err().map(|error| error.kind), Some(ErrorKind::Overflow));
+            assert_eq!(binary(MathOp::SaturatingPow, max(), two).ok(), Some(max().to_string()));
+        }
+    }
+
+    #[test]
+    fn division_by_zero_in_every_flavour() {
+        let ops = [MathOp::Div, MathOp::Rem, MathOp::WrappingDiv, MathOp::WrappingRem, MathOp::SaturatingDiv, MathOp::SaturatingRem];
+
+        for numeric_type in TYPES.iter() {
+            for op in ops.iter() {
+                let result = binary(op.clone(), numeric(numeric_type, "1"), numeric(numeric_type, "0"));
+
+                assert_eq!(result.err().map(|error| error.kind), Some(ErrorKind::DivisionByZero), "{:?} {}", op, numeric_type.suffix());
+            }
+        }
+
+        let result = binary(MathOp::Rem, Numeric::Float32(1.0), Numeric::Float32(-0.0));
+        assert_eq!(result.err().map(|error| error.kind), Some(ErrorKind::DivisionByZero));
+        assert_eq!(binary(MathOp::Div, Numeric::Float64(1.0), Numeric::Float64(4.0)).ok(), Some("0.25f64".to_string()));
+
+        // Mismatched operands are a type error before the divisor is looked at.
+        for op in ops.iter() {
+            let result = binary(op.clone(), Numeric::Int32(1), Numeric::UInt8(0));
+
+            assert_eq!(result.err().map(|error| error.kind), Some(ErrorKind::TypeMismatch), "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn int128_beyond_64_bits() {
+        let big = Numeric::Int128(i64::MAX as i128 * 4);
+        let small = Numeric::Int128(i64::MIN as i128 * 4);
+
+        assert_eq!(binary(MathOp::Add, big.clone(), small.clone()).ok(), Some("-4i128".to_string()));
+        assert_eq!(binary(MathOp::Sub, big.clone(), small.clone()).ok(), Some(format!("{}i128", (i64::MAX as i128 - i64::MIN as i128) * 4)));
+        assert_eq!(binary(MathOp::GreaterThan, big.clone(), small.clone()).ok(), Some("true".to_string()));
+        assert_eq!(binary(MathOp::LessThanEq, big.clone(), small.clone()).ok(), Some("false".to_string()));
+        assert_eq!(binary(MathOp::Eql, big.clone(), big.clone()).ok(), Some("true".to_string()));
+        assert_eq!(binary(MathOp::Shr, small.clone(), Numeric::UInt32(66)).ok(), Some(format!("{}i128", (i64::MIN as i128 * 4) >> 66)));
+
+        assert_eq!(big.clone().cast(&NumericType::UInt128).to_string(), format!("{}u128", i64::MAX as u128 * 4));
+        assert_eq!(big.clone().cast(&NumericType::Float64).to_string(), format!("{:?}f64", i64::MAX as f64 * 4.0));
+        assert_eq!(Numeric::UInt64(u64::MAX).cast(&NumericType::Int128).to_string(), format!("{}i128", u64::MAX));
+        assert_eq!(NumericType::Int128.parse(&i128::MIN.to_string()).map(|value| value.to_string()), Some(format!("{}i128", i128::MIN)));
+
+        let max = Numeric::UInt128(u128::MAX);
+        assert_eq!(binary(MathOp::Sub, max.clone(), Numeric::UInt128(1)).ok(), Some(format!("{}u128", u128::MAX - 1)));
+        assert_eq!(binary(MathOp::GreaterThan, max, Numeric::UInt128(0)).ok(), Some("true".to_string()));
+    }
+
+    #[test]
+    fn failed_ops_leave_the_stack_untouched() {
+        let operands = vec![Value::Numeric(Numeric::Int8(i8::MAX)), Value::Numeric(Numeric::Int8(1))];
+
+        let mut stack = Stack::new();
+        stack.current().borrow_mut().extend(operands);
+
+        assert!(matches!(MathOp::Add.run(&mut stack), InstructionResult::Error(_)));
+        assert_eq!(stack.current().borrow().iter().map(|value| value.to_string()).collect::<Vec<_>>(), ["127i8", "1i8"]);
+    }
+
+    fn promoted(op: MathOp, a: Numeric, b: Numeric, promotion: Promotion) -> Result<String, InstructionError> {
+        let mut stack = Stack::new();
+        stack.current().borrow_mut().extend([Value::Numeric(a), Value::Numeric(b)]);
+
+        finished(op.run_promoted(&mut stack, promotion)).map(|_| stack.current().borrow()[0].to_string())
+    }
+
+    #[test]
+    fn promotion_policies() {
+        let widen = |a, b| promoted(MathOp::Add, a, b, Promotion::Widen).ok();
+
+        assert_eq!(widen(Numeric::Int32(1), Numeric::UInt64(2)), Some("3u64".to_string()));
+        assert_eq!(widen(Numeric::UInt8(1), Numeric::Int16(2)), Some("3i16".to_string()));
+        assert_eq!(widen(Numeric::Int8(1), Numeric::Int64(2)), Some("3i64".to_string()));
+        assert_eq!(widen(Numeric::Int32(-1), Numeric::UInt32(0)), Some(format!("{}u32", u32::MAX)));
+        assert_eq!(widen(Numeric::UInt64(1), Numeric::USize(2)), Some("3u64".to_string()));
+        assert_eq!(widen(Numeric::Int128(1), Numeric::Float32(0.5)), Some("1.5f32".to_string()));
+        assert_eq!(widen(Numeric::Float32(1.0), Numeric::Float64(0.5)), Some("1.5f64".to_string()));
+
+        assert_eq!(promoted(MathOp::LessThan, Numeric::Int8(-1), Numeric::Int64(0), Promotion::Widen).ok(), Some("true".to_string()));
+        assert_eq!(promoted(MathOp::Add, Numeric::Int8(1), Numeric::UInt64(2), Promotion::Float64).ok(), Some("3.0f64".to_string()));
+        assert_eq!(promoted(MathOp::Add, Numeric::Int8(1), Numeric::Int8(2), Promotion::Float64).ok(), Some("3i8".to_string()));
+
+        // Amounts keep their own type.
+        assert_eq!(promoted(MathOp::Shl, Numeric::UInt8(1), Numeric::Int64(3), Promotion::Float64).ok(), Some("8u8".to_string()));
+        assert_eq!(promoted(MathOp::Pow, Numeric::Int16(3), Numeric::UInt8(2), Promotion::Widen).ok(), Some("9i16".to_string()));
+
+        let errors = [
+            promoted(MathOp::BitAnd, Numeric::Int8(1), Numeric::Int16(1), Promotion::Float64),
+            promoted(MathOp::Add, Numeric::Int8(1), Numeric::Int16(1), Promotion::Strict)
+        ];
+
+        for error in errors {
+            assert_eq!(error.err().map(|error| error.kind), Some(ErrorKind::TypeMismatch));
+        }
     }
 }
@@ -1,4 +1,8 @@
+use std::fmt;
+
 use crate::value::Value;
+use crate::instruction::InstructionError;
+use crate::error::ErrorKind;
 
 #[derive(Clone, Debug)]
 pub enum Numeric {
@@ -19,27 +23,66 @@ pub enum Numeric {
 }
 
 
+// Integer overflow is reported by the checked form and handled by the named method in the
+// others. Floats use the plain operator in every form.
 macro_rules! impl_math {
-    ($name:ident, $op:tt) => {
-        pub fn $name(&self, rhs: &Self) -> Option<Value> {
+    ($name:ident, $method:ident, $op:tt) => {
+        pub fn $name(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+            binary_op!(self, rhs, |a, b| int: Ok(a.$method(*b)), float: Ok(a $op b))
+        }
+    };
+    ($name:ident, $method:ident, $what:literal, $op:tt) => {
+        pub fn $name(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+            binary_op!(self, rhs, |a, b| int: a.$method(*b).ok_or_else(|| overflow($what)), float: Ok(a $op b))
+        }
+    };
+}
+
+
+// As `impl_math!`, but division by zero is always an error, for floats as well as integers.
+// Mismatched operands are reported as such, whatever the divisor.
+macro_rules! impl_division {
+    ($name:ident, $method:ident, $op:tt) => {
+        pub fn $name(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+            if self.numeric_type() != rhs.numeric_type() {
+                return Err(mismatch());
+            }
+
+            rhs.divisor()?;
+
+            binary_op!(self, rhs, |a, b| int: Ok(a.$method(*b)), float: Ok(a $op b))
+        }
+    };
+    ($name:ident, $method:ident, $what:literal, $op:tt) => {
+        pub fn $name(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+            if self.numeric_type() != rhs.numeric_type() {
+                return Err(mismatch());
+            }
+
+            rhs.divisor()?;
+
+            binary_op!(self, rhs, |a, b| int: a.$method(*b).ok_or_else(|| overflow($what)), float: Ok(a $op b))
+        }
+    };
+}
+
+
+// Integers take a non-negative exponent of any integer type, floats an exponent of their own type.
+macro_rules! impl_pow {
+    ($name:ident, |$a:ident, $amount:ident| $int:expr) => {
+        pub fn $name(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
             match (self, rhs) {
-                (Numeric::UInt8(a),   Numeric::UInt8(b)  ) => Some(Value::Numeric(Numeric::UInt8(a $op b))),
-                (Numeric::UInt16(a),  Numeric::UInt16(b) ) => Some(Value::Numeric(Numeric::UInt16(a $op b))),
-                (Numeric::UInt32(a),  Numeric::UInt32(b) ) => Some(Value::Numeric(Numeric::UInt32(a $op b))),
-                (Numeric::UInt64(a),  Numeric::UInt64(b) ) => Some(Value::Numeric(Numeric::UInt64(a $op b))),
-                (Numeric::UInt128(a), Numeric::UInt128(b)) => Some(Value::Numeric(Numeric::UInt128(a $op b))),
-                (Numeric::Int8(a),    Numeric::Int8(b)   ) => Some(Value::Numeric(Numeric::Int8(a $op b))),
-                (Numeric::Int16(a),   Numeric::Int16(b)  ) => Some(Value::Numeric(Numeric::Int16(a $op b))),
-                (Numeric::Int32(a),   Numeric::Int32(b)  ) => Some(Value::Numeric(Numeric::Int32(a $op b))),
-                (Numeric::Int64(a),   Numeric::Int64(b)  ) => Some(Value::Numeric(Numeric::Int64(a $op b))),
-                (Numeric::Float32(a), Numeric::Float32(b)) => Some(Value::Numeric(Numeric::Float32(a $op b))),
-                (Numeric::Float64(a), Numeric::Float64(b)) => Some(Value::Numeric(Numeric::Float64(a $op b))),
-                (Numeric::USize(a),   Numeric::USize(b)  ) => Some(Value::Numeric(Numeric::USize(a $op b))),
-                (Numeric::ISize(a),   Numeric::ISize(b)  ) => Some(Value::Numeric(Numeric::ISize(a $op b))),
-                _ => None
+                (Numeric::Float32(a), Numeric::Float32(b)) => Ok(Numeric::Float32(a.powf(*b))),
+                (Numeric::Float64(a), Numeric::Float64(b)) => Ok(Numeric::Float64(a.powf(*b))),
+                (Numeric::Float32(_), _) | (Numeric::Float64(_), _) => Err(mismatch()),
+                _ => {
+                    let $amount = rhs.amount("Exponent")?;
+
+                    unary_op!(self, |$a| int: $int, float_error: mismatch())
+                }
             }
         }
-    }
+    };
 }
 
 
@@ -56,6 +99,7 @@ macro_rules! impl_cmp {
                 (Numeric::Int16(a),   Numeric::Int16(b)  ) => Some(Value::Bool(a $op b)),
                 (Numeric::Int32(a),   Numeric::Int32(b)  ) => Some(Value::Bool(a $op b)),
                 (Numeric::Int64(a),   Numeric::Int64(b)  ) => Some(Value::Bool(a $op b)),
+                (Numeric::Int128(a),  Numeric::Int128(b) ) => Some(Value::Bool(a $op b)),
                 (Numeric::Float32(a), Numeric::Float32(b)) => Some(Value::Bool(a $op b)),
                 (Numeric::Float64(a), Numeric::Float64(b)) => Some(Value::Bool(a $op b)),
                 (Numeric::USize(a),   Numeric::USize(b)  ) => Some(Value::Bool(a $op b)),
@@ -67,7 +111,7 @@ macro_rules! impl_cmp {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum NumericType {
     UInt8,
     UInt16,
@@ -86,6 +130,82 @@ pub enum NumericType {
 }
 
 
+// How `MathOp` treats operands of different numeric types.
+//
+// Strict: mixed operands are a type error.
+// Widen: as C's usual arithmetic conversions, without promoting small integers to int. Any
+//   float makes the result a float, the wider of the two. Integers of the same signedness widen
+//   to the larger, and for mixed signedness the unsigned type wins unless the signed type is
+//   strictly wider. `usize` / `isize` have the target's pointer width, and a fixed width type
+//   wins a tie with them.
+// Float64: mixed operands are both converted to Float64, so integer-only ops on them fail.
+//
+// Operands of the same type, shift and rotate amounts and integer exponents are never promoted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Promotion {
+    Strict,
+    Widen,
+    Float64
+}
+
+
+impl Promotion {
+    pub fn from_name(name: &str) -> Option<Promotion> {
+        match name {
+            "strict"  => Some(Promotion::Strict),
+            "widen"   => Some(Promotion::Widen),
+            "float64" => Some(Promotion::Float64),
+            _ => None
+        }
+    }
+
+    // The type both operands are converted to, or None when they are left as they are.
+    pub fn common_type(&self, a: &NumericType, b: &NumericType) -> Option<NumericType> {
+        if a == b {
+            return None;
+        }
+
+        match self {
+            Promotion::Strict => None,
+            Promotion::Float64 => Some(NumericType::Float64),
+            Promotion::Widen => Some(widen(a, b))
+        }
+    }
+
+    pub fn promote(&self, a: Numeric, b: Numeric) -> (Numeric, Numeric) {
+        match self.common_type(&a.numeric_type(), &b.numeric_type()) {
+            Some(to) => (a.cast(&to), b.cast(&to)),
+            None => (a, b)
+        }
+    }
+}
+
+
+fn widen(a: &NumericType, b: &NumericType) -> NumericType {
+    if a.is_float() || b.is_float() {
+        return if *a == NumericType::Float64 || *b == NumericType::Float64 { NumericType::Float64 } else { NumericType::Float32 };
+    }
+
+    let pointer_sized = |numeric_type: &NumericType| matches!(numeric_type, NumericType::USize | NumericType::ISize);
+
+    let wider = |a: &NumericType, b: &NumericType| {
+        if a.bits() != b.bits() {
+            if a.bits() > b.bits() { *a } else { *b }
+        } else if pointer_sized(a) {
+            *b
+        } else {
+            *a
+        }
+    };
+
+    match (a.is_signed(), b.is_signed()) {
+        (true, true) | (false, false) => wider(a, b),
+        (true, false) => if a.bits() > b.bits() { *a } else { *b },
+        (false, true) => if b.bits() > a.bits() { *b } else { *a }
+    }
+}
+
+
 macro_rules! cast {
     ($to:ident, $value:ident) => {
         match $to {
@@ -107,6 +227,69 @@ macro_rules! cast {
     }
 }
 
+// Every numeric value fits exactly in one of these, which lets range checked casts compare
+// against the target's bounds without going through `as` first.
+#[derive(PartialEq)]
+enum Wide {
+    Int(i128),
+    UInt(u128),
+    Float(f64)
+}
+
+
+// Range checked conversion of a `Wide` to the integer type `$t`. Out of range values are clamped
+// when `$saturate` is set and `None` otherwise. NaN is always `None`, it has no integer value.
+macro_rules! int_cast {
+    ($wide:expr, $t:ty, $saturate:expr) => {
+        match $wide {
+            Wide::Int(value) => match <$t>::try_from(value) {
+                Ok(value) => Some(value),
+                Err(_) if $saturate => Some(if value < 0 { <$t>::MIN } else { <$t>::MAX }),
+                Err(_) => None
+            },
+            Wide::UInt(value) => match <$t>::try_from(value) {
+                Ok(value) => Some(value),
+                Err(_) if $saturate => Some(<$t>::MAX),
+                Err(_) => None
+            },
+            // `as` truncates towards zero and already clamps, so only the range check is needed.
+            // The bounds are powers of two, which `f64` holds exactly.
+            Wide::Float(value) => {
+                let upper = 2f64.powi(<$t>::BITS as i32 - (<$t>::MIN != 0) as i32);
+                let lower = if <$t>::MIN != 0 { -upper } else { 0.0 };
+
+                if value.is_nan() {
+                    None
+                } else if !$saturate && (value.trunc() < lower || value.trunc() >= upper) {
+                    None
+                } else {
+                    Some(value as $t)
+                }
+            }
+        }
+    }
+}
+
+
+// Conversion of a `Wide` to the float type `$t`. Only finite values too large for `$t` are out
+// of range, infinities and NaN carry over. Saturating clamps to the largest finite value.
+macro_rules! float_cast {
+    ($wide:expr, $t:ty, $saturate:expr) => {{
+        let converted = match $wide {
+            Wide::Int(value) => (value as $t, false),
+            Wide::UInt(value) => (value as $t, false),
+            Wide::Float(value) => (value as $t, value.is_infinite())
+        };
+
+        match converted {
+            (value, false) if value.is_infinite() && $saturate => Some(if value < 0.0 { <$t>::MIN } else { <$t>::MAX }),
+            (value, false) if value.is_infinite() => None,
+            (value, _) => Some(value)
+        }
+    }}
+}
+
+
 #[macro_export]
 macro_rules! cast_to_value {
     ($from:ident, $to:tt) => {
@@ -130,33 +313,499 @@ macro_rules! cast_to_value {
 }
 
 
+// Like `impl_math!`, but for operations that need more than an operator. The bound names are
+// supplied by the caller so each arm can use them, and every expression is a
+// `Result<_, InstructionError>` of the variant's own type.
+macro_rules! unary_op {
+    ($value:expr, |$a:ident| signed: $signed:expr, unsigned: $unsigned:expr, float: $float:expr) => {
+        match $value {
+            Numeric::UInt8($a)   => $unsigned.map(Numeric::UInt8),
+            Numeric::UInt16($a)  => $unsigned.map(Numeric::UInt16),
+            Numeric::UInt32($a)  => $unsigned.map(Numeric::UInt32),
+            Numeric::UInt64($a)  => $unsigned.map(Numeric::UInt64),
+            Numeric::UInt128($a) => $unsigned.map(Numeric::UInt128),
+            Numeric::USize($a)   => $unsigned.map(Numeric::USize),
+            Numeric::Int8($a)    => $signed.map(Numeric::Int8),
+            Numeric::Int16($a)   => $signed.map(Numeric::Int16),
+            Numeric::Int32($a)   => $signed.map(Numeric::Int32),
+            Numeric::Int64($a)   => $signed.map(Numeric::Int64),
+            Numeric::Int128($a)  => $signed.map(Numeric::Int128),
+            Numeric::ISize($a)   => $signed.map(Numeric::ISize),
+            Numeric::Float32($a) => $float.map(Numeric::Float32),
+            Numeric::Float64($a) => $float.map(Numeric::Float64),
+        }
+    };
+    ($value:expr, |$a:ident| int: $int:expr, float_error: $error:expr) => {
+        match $value {
+            Numeric::UInt8($a)   => $int.map(Numeric::UInt8),
+            Numeric::UInt16($a)  => $int.map(Numeric::UInt16),
+            Numeric::UInt32($a)  => $int.map(Numeric::UInt32),
+            Numeric::UInt64($a)  => $int.map(Numeric::UInt64),
+            Numeric::UInt128($a) => $int.map(Numeric::UInt128),
+            Numeric::USize($a)   => $int.map(Numeric::USize),
+            Numeric::Int8($a)    => $int.map(Numeric::Int8),
+            Numeric::Int16($a)   => $int.map(Numeric::Int16),
+            Numeric::Int32($a)   => $int.map(Numeric::Int32),
+            Numeric::Int64($a)   => $int.map(Numeric::Int64),
+            Numeric::Int128($a)  => $int.map(Numeric::Int128),
+            Numeric::ISize($a)   => $int.map(Numeric::ISize),
+            Numeric::Float32(_) | Numeric::Float64(_) => Err($error),
+        }
+    };
+}
+
+
+macro_rules! binary_op {
+    ($lhs:expr, $rhs:expr, |$a:ident, $b:ident| int: $int:expr, float: $float:expr) => {
+        match ($lhs, $rhs) {
+            (Numeric::UInt8($a),   Numeric::UInt8($b)  ) => $int.map(Numeric::UInt8),
+            (Numeric::UInt16($a),  Numeric::UInt16($b) ) => $int.map(Numeric::UInt16),
+            (Numeric::UInt32($a),  Numeric::UInt32($b) ) => $int.map(Numeric::UInt32),
+            (Numeric::UInt64($a),  Numeric::UInt64($b) ) => $int.map(Numeric::UInt64),
+            (Numeric::UInt128($a), Numeric::UInt128($b)) => $int.map(Numeric::UInt128),
+            (Numeric::USize($a),   Numeric::USize($b)  ) => $int.map(Numeric::USize),
+            (Numeric::Int8($a),    Numeric::Int8($b)   ) => $int.map(Numeric::Int8),
+            (Numeric::Int16($a),   Numeric::Int16($b)  ) => $int.map(Numeric::Int16),
+            (Numeric::Int32($a),   Numeric::Int32($b)  ) => $int.map(Numeric::Int32),
+            (Numeric::Int64($a),   Numeric::Int64($b)  ) => $int.map(Numeric::Int64),
+            (Numeric::Int128($a),  Numeric::Int128($b) ) => $int.map(Numeric::Int128),
+            (Numeric::ISize($a),   Numeric::ISize($b)  ) => $int.map(Numeric::ISize),
+            (Numeric::Float32($a), Numeric::Float32($b)) => $float.map(Numeric::Float32),
+            (Numeric::Float64($a), Numeric::Float64($b)) => $float.map(Numeric::Float64),
+            _ => Err(mismatch())
+        }
+    };
+    ($lhs:expr, $rhs:expr, |$a:ident, $b:ident| int: $int:expr, float_error: $error:expr) => {
+        match ($lhs, $rhs) {
+            (Numeric::UInt8($a),   Numeric::UInt8($b)  ) => $int.map(Numeric::UInt8),
+            (Numeric::UInt16($a),  Numeric::UInt16($b) ) => $int.map(Numeric::UInt16),
+            (Numeric::UInt32($a),  Numeric::UInt32($b) ) => $int.map(Numeric::UInt32),
+            (Numeric::UInt64($a),  Numeric::UInt64($b) ) => $int.map(Numeric::UInt64),
+            (Numeric::UInt128($a), Numeric::UInt128($b)) => $int.map(Numeric::UInt128),
+            (Numeric::USize($a),   Numeric::USize($b)  ) => $int.map(Numeric::USize),
+            (Numeric::Int8($a),    Numeric::Int8($b)   ) => $int.map(Numeric::Int8),
+            (Numeric::Int16($a),   Numeric::Int16($b)  ) => $int.map(Numeric::Int16),
+            (Numeric::Int32($a),   Numeric::Int32($b)  ) => $int.map(Numeric::Int32),
+            (Numeric::Int64($a),   Numeric::Int64($b)  ) => $int.map(Numeric::Int64),
+            (Numeric::Int128($a),  Numeric::Int128($b) ) => $int.map(Numeric::Int128),
+            (Numeric::ISize($a),   Numeric::ISize($b)  ) => $int.map(Numeric::ISize),
+            (Numeric::Float32(_),  Numeric::Float32(_) )
+            | (Numeric::Float64(_), Numeric::Float64(_)) => Err($error),
+            _ => Err(mismatch())
+        }
+    };
+}
+
+
+fn mismatch() -> InstructionError {
+    InstructionError::new(ErrorKind::TypeMismatch, "Operands not of matching numeric sub-types")
+}
+
+
+fn int_only(operation: &str) -> InstructionError {
+    InstructionError::new(ErrorKind::TypeMismatch, &format!("{} needs integer operands", operation))
+}
+
+
+fn overflow(operation: &str) -> InstructionError {
+    InstructionError::new(ErrorKind::Overflow, &format!("{} overflowed", operation))
+}
+
+
 impl Numeric {
-    impl_math!(add, +);
-    impl_math!(sub, -);
-    impl_math!(mul, *);
-    impl_math!(div, /);
+    impl_math!(add, checked_add, "Addition", +);
+    impl_math!(sub, checked_sub, "Subtraction", -);
+    impl_math!(mul, checked_mul, "Multiplication", *);
+    impl_math!(wrapping_add, wrapping_add, +);
+    impl_math!(wrapping_sub, wrapping_sub, -);
+    impl_math!(wrapping_mul, wrapping_mul, *);
+    impl_math!(saturating_add, saturating_add, +);
+    impl_math!(saturating_sub, saturating_sub, -);
+    impl_math!(saturating_mul, saturating_mul, *);
+    impl_division!(div, checked_div, "Division", /);
+    impl_division!(rem, checked_rem, "Remainder", %);
+    impl_division!(wrapping_div, wrapping_div, /);
+    impl_division!(wrapping_rem, wrapping_rem, %);
+    impl_division!(saturating_div, saturating_div, /);
+    // The only overflowing remainder is MIN % -1, whose true result 0 is what wrapping gives.
+    impl_division!(saturating_rem, wrapping_rem, %);
+    impl_pow!(pow, |a, amount| a.checked_pow(amount).ok_or_else(|| overflow("Power")));
+    impl_pow!(wrapping_pow, |a, amount| Ok(a.wrapping_pow(amount)));
+    impl_pow!(saturating_pow, |a, amount| Ok(a.saturating_pow(amount)));
     impl_cmp!(greater_than, >);
     impl_cmp!(greater_than_eq, >=);
     impl_cmp!(less_than, <);
     impl_cmp!(less_than_eq, <=);
     impl_cmp!(eq, ==);
 
+    pub fn min(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        binary_op!(self, rhs, |a, b| int: Ok(*a.min(b)), float: Ok(a.min(*b)))
+    }
+
+    pub fn max(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        binary_op!(self, rhs, |a, b| int: Ok(*a.max(b)), float: Ok(a.max(*b)))
+    }
+
+    pub fn bit_and(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        binary_op!(self, rhs, |a, b| int: Ok(a & b), float_error: int_only("Bitwise and"))
+    }
+
+    pub fn bit_or(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        binary_op!(self, rhs, |a, b| int: Ok(a | b), float_error: int_only("Bitwise or"))
+    }
+
+    pub fn bit_xor(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        binary_op!(self, rhs, |a, b| int: Ok(a ^ b), float_error: int_only("Bitwise xor"))
+    }
+
+    // Floats included, so a zero divisor never turns into an infinity or NaN that spreads
+    // through later results. Negative zero is zero too.
+    fn divisor(&self) -> Result<(), InstructionError> {
+        if self.is_zero() {
+            Err(InstructionError::new(ErrorKind::DivisionByZero, "Division by zero"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn negatable(&self) -> Result<(), InstructionError> {
+        if self.is_signed() {
+            Ok(())
+        } else {
+            Err(InstructionError::new(ErrorKind::TypeMismatch, "Cannot negate an unsigned integer"))
+        }
+    }
+
+    pub fn neg(&self) -> Result<Numeric, InstructionError> {
+        self.negatable()?;
+
+        unary_op!(self,
+            |a| signed: a.checked_neg().ok_or_else(|| overflow("Negation")),
+            unsigned: a.checked_neg().ok_or_else(|| overflow("Negation")),
+            float: Ok(-a)
+        )
+    }
+
+    pub fn wrapping_neg(&self) -> Result<Numeric, InstructionError> {
+        self.negatable()?;
+
+        unary_op!(self, |a| signed: Ok(a.wrapping_neg()), unsigned: Ok(a.wrapping_neg()), float: Ok(-a))
+    }
+
+    pub fn saturating_neg(&self) -> Result<Numeric, InstructionError> {
+        self.negatable()?;
+
+        unary_op!(self, |a| signed: Ok(a.saturating_neg()), unsigned: Ok(*a), float: Ok(-a))
+    }
+
+    pub fn abs(&self) -> Result<Numeric, InstructionError> {
+        unary_op!(self, |a| signed: a.checked_abs().ok_or_else(|| overflow("Abs")), unsigned: Ok(*a), float: Ok(a.abs()))
+    }
+
+    pub fn wrapping_abs(&self) -> Result<Numeric, InstructionError> {
+        unary_op!(self, |a| signed: Ok(a.wrapping_abs()), unsigned: Ok(*a), float: Ok(a.abs()))
+    }
+
+    pub fn saturating_abs(&self) -> Result<Numeric, InstructionError> {
+        unary_op!(self, |a| signed: Ok(a.saturating_abs()), unsigned: Ok(*a), float: Ok(a.abs()))
+    }
+
+    pub fn not(&self) -> Result<Numeric, InstructionError> {
+        unary_op!(self, |a| int: Ok(!a), float_error: int_only("Bitwise not"))
+    }
+
+    // Shift amounts and integer exponents may be any integer type, whatever the type of the
+    // value they apply to.
+    fn amount(&self, operation: &str) -> Result<u32, InstructionError> {
+        if self.is_float() {
+            return Err(InstructionError::new(ErrorKind::TypeMismatch, &format!("{} amount must be an integer", operation)));
+        }
+
+        let value = self.clone();
+        let amount = cast_to_value!(value, i128);
+
+        u32::try_from(amount).map_err(|_| {
+            InstructionError::new(ErrorKind::Overflow, &format!("{} amount {} is out of range", operation, self))
+        })
+    }
+
+    // Shifting by the bit width or more is an overflow rather than a silent wrap.
+    pub fn shl(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        let amount = rhs.amount("Shift")?;
+
+        unary_op!(self, |a| int: a.checked_shl(amount).ok_or_else(|| overflow("Shift left")), float_error: int_only("Shift left"))
+    }
+
+    // Arithmetic for signed integers, logical for unsigned.
+    pub fn shr(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        let amount = rhs.amount("Shift")?;
+
+        unary_op!(self, |a| int: a.checked_shr(amount).ok_or_else(|| overflow("Shift right")), float_error: int_only("Shift right"))
+    }
+
+    pub fn rotate_left(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        let amount = rhs.amount("Rotate")?;
+
+        unary_op!(self, |a| int: Ok(a.rotate_left(amount)), float_error: int_only("Rotate left"))
+    }
+
+    pub fn rotate_right(&self, rhs: &Self) -> Result<Numeric, InstructionError> {
+        let amount = rhs.amount("Rotate")?;
+
+        unary_op!(self, |a| int: Ok(a.rotate_right(amount)), float_error: int_only("Rotate right"))
+    }
+
     pub fn cast(self, to: &NumericType) -> Numeric {
         match self {
-            Numeric::UInt8(a)   => { return cast!(to, a); },
-            Numeric::UInt16(a)  => { return cast!(to, a); },
-            Numeric::UInt32(a)  => { return cast!(to, a); },
-            Numeric::UInt64(a)  => { return cast!(to, a); },
-            Numeric::UInt128(a) => { return cast!(to, a); },
-            Numeric::Int8(a)    => { return cast!(to, a); },
-            Numeric::Int16(a)   => { return cast!(to, a); },
-            Numeric::Int32(a)   => { return cast!(to, a); },
-            Numeric::Int64(a)   => { return cast!(to, a); },
-            Numeric::Int128(a)  => { return cast!(to, a); },
-            Numeric::Float32(a) => { return cast!(to, a); },
-            Numeric::Float64(a) => { return cast!(to, a); },
-            Numeric::USize(a)   => { return cast!(to, a); },
-            Numeric::ISize(a)   => { return cast!(to, a); },
-        };
+            Numeric::UInt8(a)   => cast!(to, a),
+            Numeric::UInt16(a)  => cast!(to, a),
+            Numeric::UInt32(a)  => cast!(to, a),
+            Numeric::UInt64(a)  => cast!(to, a),
+            Numeric::UInt128(a) => cast!(to, a),
+            Numeric::Int8(a)    => cast!(to, a),
+            Numeric::Int16(a)   => cast!(to, a),
+            Numeric::Int32(a)   => cast!(to, a),
+            Numeric::Int64(a)   => cast!(to, a),
+            Numeric::Int128(a)  => cast!(to, a),
+            Numeric::Float32(a) => cast!(to, a),
+            Numeric::Float64(a) => cast!(to, a),
+            Numeric::USize(a)   => cast!(to, a),
+            Numeric::ISize(a)   => cast!(to, a),
+        }
+    }
+
+    fn wide(&self) -> Wide {
+        match *self {
+            Numeric::UInt8(a)   => Wide::UInt(a as u128),
+            Numeric::UInt16(a)  => Wide::UInt(a as u128),
+            Numeric::UInt32(a)  => Wide::UInt(a as u128),
+            Numeric::UInt64(a)  => Wide::UInt(a as u128),
+            Numeric::UInt128(a) => Wide::UInt(a),
+            Numeric::USize(a)   => Wide::UInt(a as u128),
+            Numeric::Int8(a)    => Wide::Int(a as i128),
+            Numeric::Int16(a)   => Wide::Int(a as i128),
+            Numeric::Int32(a)   => Wide::Int(a as i128),
+            Numeric::Int64(a)   => Wide::Int(a as i128),
+            Numeric::Int128(a)  => Wide::Int(a),
+            Numeric::ISize(a)   => Wide::Int(a as i128),
+            Numeric::Float32(a) => Wide::Float(a as f64),
+            Numeric::Float64(a) => Wide::Float(a),
+        }
+    }
+
+    fn convert(&self, to: &NumericType, saturate: bool) -> Option<Numeric> {
+        let wide = self.wide();
+
+        match to {
+            NumericType::UInt8   => int_cast!(wide, u8, saturate).map(Numeric::UInt8),
+            NumericType::UInt16  => int_cast!(wide, u16, saturate).map(Numeric::UInt16),
+            NumericType::UInt32  => int_cast!(wide, u32, saturate).map(Numeric::UInt32),
+            NumericType::UInt64  => int_cast!(wide, u64, saturate).map(Numeric::UInt64),
+            NumericType::UInt128 => int_cast!(wide, u128, saturate).map(Numeric::UInt128),
+            NumericType::USize   => int_cast!(wide, usize, saturate).map(Numeric::USize),
+            NumericType::Int8    => int_cast!(wide, i8, saturate).map(Numeric::Int8),
+            NumericType::Int16   => int_cast!(wide, i16, saturate).map(Numeric::Int16),
+            NumericType::Int32   => int_cast!(wide, i32, saturate).map(Numeric::Int32),
+            NumericType::Int64   => int_cast!(wide, i64, saturate).map(Numeric::Int64),
+            NumericType::Int128  => int_cast!(wide, i128, saturate).map(Numeric::Int128),
+            NumericType::ISize   => int_cast!(wide, isize, saturate).map(Numeric::ISize),
+            NumericType::Float32 => float_cast!(wide, f32, saturate).map(Numeric::Float32),
+            NumericType::Float64 => float_cast!(wide, f64, saturate).map(Numeric::Float64),
+        }
+    }
+
+    // Unlike `cast`, fails instead of wrapping, clamping or rounding a value that doesn't fit in
+    // `to`. A float only fits an integer type when it has no fraction to lose, and an integer
+    // only fits a float type when the float holds it exactly.
+    pub fn checked_cast(&self, to: &NumericType) -> Result<Numeric, InstructionError> {
+        if let Wide::Float(value) = self.wide() {
+            if !to.is_float() && value.is_finite() && value.fract() != 0.0 {
+                return Err(InstructionError::new(ErrorKind::OutOfRange, &format!("{} has a fraction, which {} can't hold", self, to.suffix())));
+            }
+        }
+
+        let out_of_range = || InstructionError::new(ErrorKind::OutOfRange, &format!("{} doesn't fit in {}", self, to.suffix()));
+        let result = self.convert(to, false).ok_or_else(out_of_range)?;
+
+        if !self.is_float() && to.is_float() {
+            let back = result.convert(&self.numeric_type(), false).map(|back| back.wide());
+
+            if back != Some(self.wide()) {
+                return Err(InstructionError::new(ErrorKind::OutOfRange, &format!("{} can't be held exactly by {}", self, to.suffix())));
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Clamps values that don't fit in `to` to its bounds. NaN still fails.
+    pub fn saturating_cast(&self, to: &NumericType) -> Result<Numeric, InstructionError> {
+        self.convert(to, true).ok_or_else(|| {
+            InstructionError::new(ErrorKind::OutOfRange, &format!("{} can't be saturated to {}", self, to.suffix()))
+        })
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Numeric::Float32(_) | Numeric::Float64(_))
+    }
+
+    // Floats count as signed.
+    pub fn is_signed(&self) -> bool {
+        !matches!(
+            self,
+            Numeric::UInt8(_) | Numeric::UInt16(_) | Numeric::UInt32(_) | Numeric::UInt64(_) | Numeric::UInt128(_) | Numeric::USize(_)
+        )
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Numeric::UInt8(a)   => *a == 0,
+            Numeric::UInt16(a)  => *a == 0,
+            Numeric::UInt32(a)  => *a == 0,
+            Numeric::UInt64(a)  => *a == 0,
+            Numeric::UInt128(a) => *a == 0,
+            Numeric::Int8(a)    => *a == 0,
+            Numeric::Int16(a)   => *a == 0,
+            Numeric::Int32(a)   => *a == 0,
+            Numeric::Int64(a)   => *a == 0,
+            Numeric::Int128(a)  => *a == 0,
+            Numeric::Float32(a) => *a == 0.0,
+            Numeric::Float64(a) => *a == 0.0,
+            Numeric::USize(a)   => *a == 0,
+            Numeric::ISize(a)   => *a == 0,
+        }
+    }
+
+    pub fn numeric_type(&self) -> NumericType {
+        match self {
+            Numeric::UInt8(_)   => NumericType::UInt8,
+            Numeric::UInt16(_)  => NumericType::UInt16,
+            Numeric::UInt32(_)  => NumericType::UInt32,
+            Numeric::UInt64(_)  => NumericType::UInt64,
+            Numeric::UInt128(_) => NumericType::UInt128,
+            Numeric::Int8(_)    => NumericType::Int8,
+            Numeric::Int16(_)   => NumericType::Int16,
+            Numeric::Int32(_)   => NumericType::Int32,
+            Numeric::Int64(_)   => NumericType::Int64,
+            Numeric::Int128(_)  => NumericType::Int128,
+            Numeric::Float32(_) => NumericType::Float32,
+            Numeric::Float64(_) => NumericType::Float64,
+            Numeric::USize(_)   => NumericType::USize,
+            Numeric::ISize(_)   => NumericType::ISize,
+        }
+    }
+}
+
+
+impl Numeric {
+    // The value without its type suffix, in a form `NumericType::parse` reads back.
+    pub fn digits(&self) -> String {
+        match self {
+            Numeric::UInt8(a)   => a.to_string(),
+            Numeric::UInt16(a)  => a.to_string(),
+            Numeric::UInt32(a)  => a.to_string(),
+            Numeric::UInt64(a)  => a.to_string(),
+            Numeric::UInt128(a) => a.to_string(),
+            Numeric::Int8(a)    => a.to_string(),
+            Numeric::Int16(a)   => a.to_string(),
+            Numeric::Int32(a)   => a.to_string(),
+            Numeric::Int64(a)   => a.to_string(),
+            Numeric::Int128(a)  => a.to_string(),
+            Numeric::Float32(a) => format!("{:?}", a),
+            Numeric::Float64(a) => format!("{:?}", a),
+            Numeric::USize(a)   => a.to_string(),
+            Numeric::ISize(a)   => a.to_string(),
+        }
+    }
+}
+
+
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.digits(), self.numeric_type().suffix())
+    }
+}
+
+
+impl NumericType {
+    pub fn from_suffix(suffix: &str) -> Option<NumericType> {
+        match suffix {
+            "u8"    => Some(NumericType::UInt8),
+            "u16"   => Some(NumericType::UInt16),
+            "u32"   => Some(NumericType::UInt32),
+            "u64"   => Some(NumericType::UInt64),
+            "u128"  => Some(NumericType::UInt128),
+            "i8"    => Some(NumericType::Int8),
+            "i16"   => Some(NumericType::Int16),
+            "i32"   => Some(NumericType::Int32),
+            "i64"   => Some(NumericType::Int64),
+            "i128"  => Some(NumericType::Int128),
+            "f32"   => Some(NumericType::Float32),
+            "f64"   => Some(NumericType::Float64),
+            "usize" => Some(NumericType::USize),
+            "isize" => Some(NumericType::ISize),
+            _ => None
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, NumericType::Float32 | NumericType::Float64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        !matches!(
+            self,
+            NumericType::UInt8 | NumericType::UInt16 | NumericType::UInt32 | NumericType::UInt64 | NumericType::UInt128 | NumericType::USize
+        )
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            NumericType::UInt8   | NumericType::Int8    => 8,
+            NumericType::UInt16  | NumericType::Int16   => 16,
+            NumericType::UInt32  | NumericType::Int32   | NumericType::Float32 => 32,
+            NumericType::UInt64  | NumericType::Int64   | NumericType::Float64 => 64,
+            NumericType::UInt128 | NumericType::Int128  => 128,
+            NumericType::USize   => usize::BITS,
+            NumericType::ISize   => isize::BITS,
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            NumericType::UInt8   => "u8",
+            NumericType::UInt16  => "u16",
+            NumericType::UInt32  => "u32",
+            NumericType::UInt64  => "u64",
+            NumericType::UInt128 => "u128",
+            NumericType::Int8    => "i8",
+            NumericType::Int16   => "i16",
+            NumericType::Int32   => "i32",
+            NumericType::Int64   => "i64",
+            NumericType::Int128  => "i128",
+            NumericType::Float32 => "f32",
+            NumericType::Float64 => "f64",
+            NumericType::USize   => "usize",
+            NumericType::ISize   => "isize",
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Option<Numeric> {
+        match self {
+            NumericType::UInt8   => text.parse().ok().map(Numeric::UInt8),
+            NumericType::UInt16  => text.parse().ok().map(Numeric::UInt16),
+            NumericType::UInt32  => text.parse().ok().map(Numeric::UInt32),
+            NumericType::UInt64  => text.parse().ok().map(Numeric::UInt64),
+            NumericType::UInt128 => text.parse().ok().map(Numeric::UInt128),
+            NumericType::Int8    => text.parse().ok().map(Numeric::Int8),
+            NumericType::Int16   => text.parse().ok().map(Numeric::Int16),
+            NumericType::Int32   => text.parse().ok().map(Numeric::Int32),
+            NumericType::Int64   => text.parse().ok().map(Numeric::Int64),
+            NumericType::Int128  => text.parse().ok().map(Numeric::Int128),
+            NumericType::Float32 => text.parse().ok().map(Numeric::Float32),
+            NumericType::Float64 => text.parse().ok().map(Numeric::Float64),
+            NumericType::USize   => text.parse().ok().map(Numeric::USize),
+            NumericType::ISize   => text.parse().ok().map(Numeric::ISize),
+        }
     }
 }
@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::function::Function;
+use crate::ptr::Ptr;
+use crate::value::{Value, ValueType};
+use crate::numeric::Numeric;
+use crate::instruction::Instruction;
+use crate::control_op::ControlOp;
+use crate::struct_op::StructOp;
+use crate::struct_def::StructDef;
+
+
+#[derive(Debug)]
+pub struct Program {
+    pub functions: HashMap<usize, Function>,
+    pub structs: HashMap<String, Rc<StructDef>>,
+    pub labels: HashMap<String, usize>,
+    pub ptrs: Vec<(String, Ptr)>,
+    pub exports: HashSet<String>
+}
+
+
+impl Program {
+    pub fn new(functions: HashMap<usize, Function>) -> Program {
+        Program {
+            functions,
+            structs: HashMap::new(),
+            labels: HashMap::new(),
+            ptrs: vec![],
+            exports: HashSet::new()
+        }
+    }
+
+    pub fn function_id(&self, name: &str) -> Option<usize> {
+        self.labels.get(name).copied()
+    }
+
+    pub fn function_name(&self, id: usize) -> Option<&str> {
+        self.labels.iter()
+            .find(|(_, label_id)| **label_id == id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn ptr(&self, name: &str) -> Option<&Ptr> {
+        self.ptrs.iter()
+            .find(|(ptr_name, _)| ptr_name == name)
+            .map(|(_, ptr)| ptr)
+    }
+
+    // Static checks that don't need to run the program: every constant call target and struct
+    // type must exist, and every jump and local must be inside its function.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        let mut ids: Vec<&usize> = self.functions.keys().collect();
+        ids.sort();
+
+        for id in ids {
+            let name = self.function_name(*id).map_or_else(|| id.to_string(), |name| name.to_string());
+
+            let length = self.functions[id].instructions.len();
+            let locals = self.functions[id].ptr_recipie.len();
+
+            for (index, instruction) in self.functions[id].instructions.iter().enumerate() {
+                for local in instruction.locals().into_iter().filter(|local| *local >= locals) {
+                    problems.push(format!("fn {} instruction {}: local ${} is outside of the function's {} local(s)", name, index, local, locals));
+                }
+
+                let target = match instruction {
+                    Instruction::Control(ControlOp::Jump(target))
+                    | Instruction::Control(ControlOp::JumpIf(target, _))
+                    | Instruction::Control(ControlOp::JumpElse(target, _)) => {
+                        if target.resolve(index).is_none_or(|target| target > length) {
+                            problems.push(format!("fn {} instruction {}: jump target {} is outside of the function", name, index, target));
+                        }
+
+                        continue;
+                    },
+                    Instruction::Struct(StructOp::New(struct_name)) => {
+                        if !self.structs.contains_key(struct_name) {
+                            problems.push(format!("fn {} instruction {}: struct type {} is not declared", name, index, struct_name));
+                        }
+
+                        continue;
+                    },
+                    Instruction::Control(ControlOp::Call(target))
+                    | Instruction::Control(ControlOp::TailCall(target))
+                    | Instruction::Control(ControlOp::CallIf(target, _))
+                    | Instruction::Control(ControlOp::CallElse(target, _)) => target,
+                    _ => continue
+                };
+
+                match target {
+                    ValueType::Value(Value::Numeric(Numeric::USize(target))) if !self.functions.contains_key(target) => {
+                        problems.push(format!("fn {} instruction {}: call to missing function {}", name, index, target));
+                    },
+                    ValueType::Value(Value::Numeric(Numeric::USize(_))) | ValueType::Ptr(_) | ValueType::StackValue | ValueType::Local(_) => {},
+                    ValueType::Value(value) => {
+                        problems.push(format!("fn {} instruction {}: call target {} is not a usize", name, index, value));
+                    }
+                }
+            }
+        }
+
+        problems
+    }
+
+    pub fn exported_ptrs(&self) -> impl Iterator<Item = &(String, Ptr)> {
+        self.ptrs.iter().filter(|(name, _)| self.exports.contains(name))
+    }
+}
+
+
+// Walks depth first without recursing, since ptrs made by `alloc` can form long chains.
+fn collect_value_ptrs(value: &Value, seen: &mut HashSet<usize>, ptrs: &mut Vec<Ptr>) {
+    let mut pending = vec![];
+    value.collect_ptrs(&mut pending);
+    pending.reverse();
+
+    while let Some(ptr) = pending.pop() {
+        if !seen.insert(ptr.address()) {
+            continue;
+        }
+
+        let mut inner = vec![];
+        ptr.value.borrow().collect_ptrs(&mut inner);
+        pending.extend(inner.into_iter().rev());
+
+        ptrs.push(ptr);
+    }
+}
+
+
+// Every ptr reachable from the named ptrs and the functions, in a stable order:
+// named ptrs first, then by function id, recipe before instructions.
+pub fn collect_ptrs(functions: &HashMap<usize, Function>, named_ptrs: &[(String, Ptr)]) -> Vec<Ptr> {
+    let mut seen = HashSet::new();
+    let mut ptrs = vec![];
+
+    for (_, ptr) in named_ptrs {
+        collect_value_ptrs(&Value::Ptr(ptr.clone()), &mut seen, &mut ptrs);
+    }
+
+    let mut ids: Vec<&usize> = functions.keys().collect();
+    ids.sort();
+
+    for id in ids {
+        let function = &functions[id];
+
+        for value in &function.ptr_recipie {
+            collect_value_ptrs(value, &mut seen, &mut ptrs);
+        }
+
+        for instruction in &function.instructions {
+            for ptr in instruction.ptrs() {
+                collect_value_ptrs(&Value::Ptr(ptr.clone()), &mut seen, &mut ptrs);
+            }
+        }
+    }
+
+    ptrs
+}
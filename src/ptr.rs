@@ -16,6 +16,10 @@ impl Ptr {
             value: Rc::new(RefCell::new(value))
         }
     }
+
+    pub fn address(&self) -> usize {
+        Rc::as_ptr(&self.value) as usize
+    }
 }
 
 
@@ -0,0 +1,229 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::mem;
+
+use crate::stack::{Stack, format_values};
+use crate::instruction::Instruction;
+use crate::function::{Function, FunctionController};
+use crate::program::Program;
+use crate::heap::Heap;
+use crate::assembler;
+use crate::disassembler;
+use crate::bytecode;
+use crate::cli;
+
+
+// Single instructions typed at the prompt run as this function, on the base stack.
+const REPL_FN: usize = usize::MAX;
+
+const HELP: &str = "\
+Type an instruction to run it, or define functions, ptrs and structs as in a .vmasm file.
+A `fn` definition continues until its `end` line.
+
+    :stack        show the current substack and the full stack of stacks
+    :fns          list defined functions
+    :reset        clear the stack
+    :load <file>  load functions and ptrs from assembly or bytecode
+    :save <file>  save functions and ptrs, as bytecode if the file ends in .vmbc
+    :heap         show garbage collector stats
+    :help         show this message
+    :quit         leave the repl";
+
+
+struct Repl {
+    program: Program,
+    stack: Stack,
+    heap: Heap
+}
+
+
+impl Repl {
+    fn execute(&mut self, instruction: Instruction) -> Result<(), String> {
+        self.program.functions.insert(REPL_FN, Function {
+            ptr_recipie: vec![],
+            instructions: vec![instruction],
+            param_count: 0,
+            return_count: 0
+        });
+
+        let functions = mem::take(&mut self.program.functions);
+        let stack = mem::replace(&mut self.stack, Stack::new());
+        let heap = mem::replace(&mut self.heap, Heap::new());
+
+        let mut controller = FunctionController::resume(functions, stack, heap);
+        controller.set_structs(self.program.structs.clone());
+
+        for (_, ptr) in &self.program.ptrs {
+            controller.add_root(ptr.clone());
+        }
+
+        let result = controller.call(REPL_FN);
+        (self.program.functions, self.stack, self.heap) = controller.into_parts();
+
+        self.program.functions.remove(&REPL_FN);
+
+        result.map_err(|error| error.to_string())
+    }
+
+    // Functions in `program` replace any existing function with the same id, along with its label,
+    // and structs replace any struct of the same name.
+    fn merge(&mut self, program: Program) {
+        self.program.labels.retain(|_, id| !program.functions.contains_key(id));
+        self.program.functions.extend(program.functions);
+        self.program.structs.extend(program.structs);
+        self.program.labels.extend(program.labels);
+        self.program.exports.extend(program.exports);
+
+        for (name, ptr) in program.ptrs {
+            match self.program.ptrs.iter_mut().find(|(existing, _)| *existing == name) {
+                Some(existing) => existing.1 = ptr,
+                None => self.program.ptrs.push((name, ptr))
+            }
+        }
+    }
+
+    fn define(&mut self, source: &str) -> Result<(), String> {
+        let program = assembler::assemble_into(source, &self.program).map_err(|error| error.to_string())?;
+
+        self.merge(program);
+
+        Ok(())
+    }
+
+    fn show(&self, output: &mut impl Write) -> io::Result<()> {
+        writeln!(output, "current: {}", format_values(&self.stack.current().borrow()))?;
+        writeln!(output, "stacks:  {}", self.stack)
+    }
+
+    fn functions(&self, output: &mut impl Write) -> io::Result<()> {
+        let mut ids: Vec<&usize> = self.program.functions.keys().collect();
+        ids.sort();
+
+        for id in ids {
+            let function = &self.program.functions[id];
+
+            writeln!(
+                output,
+                "{}: {} params={} returns={} ({} instructions)",
+                id,
+                self.program.function_name(*id).unwrap_or("-"),
+                function.param_count,
+                function.return_count,
+                function.instructions.len()
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn save(&self, path: &str) -> Result<(), String> {
+        let contents = if path.ends_with(".vmbc") {
+            bytecode::encode(&self.program).map_err(|error| format!("{}: {}", path, error))?
+        } else if let Some((name, data_type)) = disassembler::unwritable_ptr(&self.program) {
+            return Err(format!("Ptr {} holds a value of type {}, which has no assembly form, save to a .vmbc file instead", name, data_type));
+        } else {
+            disassembler::disassemble(&self.program).into_bytes()
+        };
+
+        fs::write(path, contents).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    // Returns false when the session should end.
+    fn command(&mut self, line: &str, output: &mut impl Write) -> io::Result<bool> {
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let command = parts.next().unwrap_or("");
+        let argument = parts.next().map(str::trim).unwrap_or("");
+
+        let result = match (command, argument) {
+            (":quit", _) | (":q", _) => return Ok(false),
+            (":help", _) => writeln!(output, "{}", HELP).map(|_| Ok(())),
+            (":stack", _) => self.show(output).map(|_| Ok(())),
+            (":fns", _) => self.functions(output).map(|_| Ok(())),
+            (":heap", _) => writeln!(output, "heap: {}", self.heap.stats()).map(|_| Ok(())),
+            (":reset", _) => {
+                self.stack = Stack::new();
+                self.show(output).map(|_| Ok(()))
+            },
+            (":load", "") | (":save", "") => Ok(Err(format!("{} needs a file", command))),
+            (":load", path) => Ok(cli::load(path).map(|program| self.merge(program))),
+            (":save", path) => Ok(self.save(path)),
+            _ => Ok(Err(format!("Unknown command '{}', try :help", command)))
+        }?;
+
+        if let Err(error) = result {
+            writeln!(output, "error: {}", error)?;
+        }
+
+        Ok(true)
+    }
+
+    fn session(&mut self, input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+        let mut definition: Option<String> = None;
+
+        write!(output, "> ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let trimmed = line.trim();
+            let keyword = trimmed.split_whitespace().next().unwrap_or("");
+
+            if let Some(source) = &mut definition {
+                source.push_str(&line);
+                source.push('\n');
+
+                if keyword == "end" {
+                    let source = definition.take().unwrap();
+
+                    if let Err(error) = self.define(&source) {
+                        writeln!(output, "error: {}", error)?;
+                    }
+                }
+            } else if trimmed.is_empty() || trimmed.starts_with(';') {
+            } else if trimmed.starts_with(':') {
+                if !self.command(trimmed, output)? {
+                    return Ok(());
+                }
+            } else if keyword == "fn" {
+                definition = Some(format!("{}\n", line));
+            } else if keyword == "ptr" || keyword == "export" || keyword == "struct" {
+                if let Err(error) = self.define(&line) {
+                    writeln!(output, "error: {}", error)?;
+                }
+            } else {
+                match assembler::assemble_instruction(&line, &self.program) {
+                    Ok(instruction) => {
+                        if let Err(error) = self.execute(instruction) {
+                            writeln!(output, "error: {}", error)?;
+                        }
+
+                        self.show(output)?;
+                    },
+                    Err(error) => writeln!(output, "error: {}", error)?
+                }
+            }
+
+            write!(output, "{}", if definition.is_some() { ".. " } else { "> " })?;
+            output.flush()?;
+        }
+
+        writeln!(output)
+    }
+}
+
+
+pub fn run(input: impl BufRead, output: &mut impl Write) -> i32 {
+    let mut repl = Repl {
+        program: Program::new(Default::default()),
+        stack: Stack::new(),
+        heap: Heap::new()
+    };
+
+    match repl.session(input, output) {
+        Ok(()) => cli::EXIT_OK,
+        Err(error) => {
+            eprintln!("{}", error);
+            cli::EXIT_LOAD_ERROR
+        }
+    }
+}
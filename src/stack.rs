@@ -1,3 +1,4 @@
+use std::fmt;
 use std::rc::Rc;
 use std::cell::RefCell;
 
@@ -22,14 +23,60 @@ impl Stack {
         self.stacks.last().unwrap().clone()
     }
 
-    pub fn substack(&mut self, carry_count: usize) {
-        let last_items = self.current().borrow().iter().rev().take(carry_count).rev().cloned().collect();
+    pub fn depth(&self) -> usize {
+        self.stacks.len()
+    }
+
+    pub fn snapshot(&self) -> Vec<Vec<Value>> {
+        self.stacks.iter().map(|stack| stack.borrow().clone()).collect()
+    }
+
+    pub fn truncate(&mut self, depth: usize) {
+        self.stacks.truncate(depth.max(1));
+    }
+
+    // Both return how many values were actually carried, which is less than `carry_count`
+    // when the stack is shorter.
+    pub fn substack(&mut self, carry_count: usize) -> usize {
+        let last_items: Vec<Value> = self.current().borrow().iter().rev().take(carry_count).rev().cloned().collect();
+        let carried = last_items.len();
         self.stacks.push(Rc::new(RefCell::new(last_items)));
+
+        carried
+    }
+
+    // Keeps only the top `carry_count` values of the current substack, for tail calls.
+    pub fn rebase(&mut self, carry_count: usize) -> usize {
+        let current = self.current();
+        let mut current = current.borrow_mut();
+        let start = current.len().saturating_sub(carry_count);
+        current.drain(..start);
+
+        current.len()
     }
 
-    pub fn destack(&mut self, carry_count: usize) {
-        let mut last_items = self.current().borrow().iter().rev().take(carry_count).rev().cloned().collect();
+    pub fn destack(&mut self, carry_count: usize) -> usize {
+        let mut last_items: Vec<Value> = self.current().borrow().iter().rev().take(carry_count).rev().cloned().collect();
+        let carried = last_items.len();
         self.stacks.pop();
         self.current().borrow_mut().append(&mut last_items);
+
+        carried
+    }
+}
+
+
+pub fn format_values(values: &[Value]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+
+    format!("[{}]", values.join(", "))
+}
+
+
+impl fmt::Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stacks: Vec<String> = self.stacks.iter().map(|stack| format_values(&stack.borrow())).collect();
+
+        write!(f, "[{}]", stacks.join(", "))
     }
 }
@@ -1,11 +1,13 @@
 use crate::numeric::Numeric;
 use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
 use crate::value::{ValueType, Value};
 use crate::ptr::Ptr;
 use crate::stack::Stack;
+use crate::heap::Heap;
 use crate::cast_to_value;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum StackOp {
     Swap,
     Duplicate,
@@ -18,11 +20,18 @@ pub enum StackOp {
     Destack(ValueType),
     Len,
     Inspect,
+    // value -> a new ptr holding value
+    Alloc,
+    // ptr, value -> nothing, writing value through ptr
+    Store,
+    // value -> nothing, writing value to a local of the running call
+    StoreLocal(usize),
 }
 
 
-impl Runnable for StackOp {
-    fn run(&self, stack: &mut Stack) -> InstructionResult {
+impl StackOp {
+    // `Alloc` makes its ptrs in `heap`, so they can be collected once unreachable.
+    pub fn run_with(&self, stack: &mut Stack, heap: &mut Heap) -> InstructionResult {
         let current_stack = stack.current();
 
         match self {
@@ -32,7 +41,7 @@ impl Runnable for StackOp {
                 let len = current_stack.len();
 
                 if len < 2 {
-                    InstructionResult::Error(InstructionError::new("Stack too short to perform swap"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Stack too short to perform swap"))
                 } else {
                     let tmp = current_stack[len - 1].clone();
                     current_stack[len - 1] = current_stack[len - 2].clone();
@@ -51,7 +60,7 @@ impl Runnable for StackOp {
 
                     InstructionResult::None
                 } else {
-                    InstructionResult::Error(InstructionError::new("No item on stack to duplicate"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "No item on stack to duplicate"))
                 }
             },
             StackOp::Pop(ptr) => {
@@ -62,7 +71,7 @@ impl Runnable for StackOp {
 
                     InstructionResult::None
                 } else {
-                    InstructionResult::Error(InstructionError::new("No item on stack to pop"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "No item on stack to pop"))
                 }
 
             },
@@ -78,7 +87,7 @@ impl Runnable for StackOp {
                     current_stack.borrow_mut().push(value);
                     InstructionResult::None
                 } else {
-                    InstructionResult::Error(InstructionError::new("Failed to get value"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to get value"))
                 }
             },
             StackOp::PushPtr(ptr) => {
@@ -97,37 +106,45 @@ impl Runnable for StackOp {
 
                         InstructionResult::None
                     } else {
-                        InstructionResult::Error(InstructionError::new("Last item on stack not ptr"))
+                        InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Last item on stack not ptr"))
                     }
                 } else {
-                    InstructionResult::Error(InstructionError::new("No item on stack to DeRef"))
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "No item on stack to DeRef"))
                 }
             },
             StackOp::SubStack(value) => { 
                 let value = value.to_value(current_stack.borrow().last());
 
-                if let Some(Value::Numeric(value)) = value {
-                    stack.substack(cast_to_value!(value, usize)); 
+                match value {
+                    Some(Value::Numeric(value)) => {
+                        stack.substack(cast_to_value!(value, usize));
 
-                    InstructionResult::None
-                } else {
-                    InstructionResult::Error(InstructionError::new("Failed to read numeric value"))
+                        InstructionResult::None
+                    },
+                    Some(_) => InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Carry count must be numeric")),
+                    None => InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to read numeric value"))
                 }
                 
             },
             StackOp::Destack(value) => { 
                 let value = value.to_value(current_stack.borrow().last());
 
-                if let Some(Value::Numeric(value)) = value {
-                    stack.destack(cast_to_value!(value, usize)); 
+                match value {
+                    Some(_) if stack.depth() < 2 => {
+                        InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "No substack to destack"))
+                    },
+                    Some(Value::Numeric(value)) => {
+                        stack.destack(cast_to_value!(value, usize));
 
-                    InstructionResult::None
-                } else {
-                    InstructionResult::Error(InstructionError::new("Failed to read numeric value"))
+                        InstructionResult::None
+                    },
+                    Some(_) => InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Carry count must be numeric")),
+                    None => InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Failed to read numeric value"))
                 }
             },
             StackOp::Len => { 
-                current_stack.borrow_mut().push(Value::Numeric(Numeric::USize(current_stack.borrow().len())));
+                let len = current_stack.borrow().len();
+                current_stack.borrow_mut().push(Value::Numeric(Numeric::USize(len)));
 
                 InstructionResult::None
             },
@@ -135,7 +152,110 @@ impl Runnable for StackOp {
                 println!("Inspect: {:?}", current_stack);
 
                 InstructionResult::None
+            },
+            StackOp::Alloc => {
+                let mut current_stack = current_stack.borrow_mut();
+
+                if let Some(value) = current_stack.pop() {
+                    current_stack.push(Value::Ptr(heap.alloc(value)));
+
+                    InstructionResult::None
+                } else {
+                    InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "No item on stack to alloc"))
+                }
+            },
+            StackOp::Store => {
+                let mut current_stack = current_stack.borrow_mut();
+
+                let len = current_stack.len();
+
+                if len < 2 {
+                    return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Store needs a ptr and a value on the stack"));
+                }
+
+                if let Value::Ptr(ptr) = &current_stack[len - 2] {
+                    ptr.value.replace(current_stack[len - 1].clone());
+                    current_stack.truncate(len - 2);
+
+                    InstructionResult::None
+                } else {
+                    InstructionResult::Error(InstructionError::new(ErrorKind::TypeMismatch, "Item below the value to store is not a ptr"))
+                }
+            },
+            // The controller runs this as a `Pop` into the local, see `Instruction::with_locals`.
+            StackOp::StoreLocal(index) => {
+                InstructionResult::Error(InstructionError::new(ErrorKind::OutOfRange, &format!("Local ${} used outside of a call", index)))
             }
         }
     }
 }
+
+
+impl Runnable for StackOp {
+    fn run(&self, stack: &mut Stack) -> InstructionResult {
+        self.run_with(stack, &mut Heap::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::testing::{finished, push, contents};
+    use crate::function::testing::run;
+
+    fn store(stack: &mut Stack) -> Result<(), ErrorKind> {
+        finished(StackOp::Store.run_with(stack, &mut Heap::new())).map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn store_writes_the_top_through_the_ptr_below() {
+        let source = "
+            fn main params=0 returns=0
+                push 1i32
+                alloc
+                dup
+                push 2i32
+                store
+                deref
+            end
+        ";
+
+        assert_eq!(run(source), ["2i32"]);
+    }
+
+    #[test]
+    fn store_checks_its_operands() {
+        let mut stack = Stack::new();
+        push(&mut stack, Value::Ptr(Ptr::new(Value::Bool(false))));
+
+        assert_eq!(store(&mut stack), Err(ErrorKind::StackUnderflow));
+        assert_eq!(contents(&stack).len(), 1);
+
+        // The value on top and the ptr below it, not the other way round.
+        push(&mut stack, Value::Bool(true));
+        stack.current().borrow_mut().swap(0, 1);
+
+        assert_eq!(store(&mut stack), Err(ErrorKind::TypeMismatch));
+        assert_eq!(contents(&stack).len(), 2);
+    }
+
+    #[test]
+    fn separate_allocs_are_separate_ptrs() {
+        let source = "
+            fn main params=0 returns=0
+                push 1i32
+                alloc
+                push 1i32
+                alloc
+                equal
+                push 1i32
+                alloc
+                dup
+                equal
+            end
+        ";
+
+        assert_eq!(run(source), ["false", "true"]);
+    }
+}
@@ -0,0 +1,263 @@
+use std::iter;
+
+use crate::stack::Stack;
+use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
+use crate::numeric::{Numeric, NumericType};
+use crate::value::Value;
+
+// Positions and lengths are `usize` numerics counted in chars, except `Len` which counts bytes.
+#[derive(Debug, Clone)]
+pub enum StrOp {
+    // a, b -> a followed by b
+    Concat,
+    // s -> bytes in s
+    Len,
+    // s -> chars in s
+    CharLen,
+    // s, start, end -> the chars of s from start up to but not including end
+    Substring,
+    // s, needle -> char index of the first needle in s, true; or the char length of s, false
+    IndexOf,
+    // s, separator -> each piece, then the piece count. An empty separator splits into chars.
+    Split,
+    // pieces..., count, separator -> the count pieces joined with separator between them
+    Join,
+    Trim,
+    Upper,
+    Lower,
+    // s, prefix -> bool
+    StartsWith,
+    // s, suffix -> bool
+    EndsWith,
+    // a, b -> bool, comparing byte by byte
+    Eql,
+    GreaterThan,
+    LessThan,
+    GreaterThanEq,
+    LessThanEq,
+    // numeric -> its digits without the type suffix, such as "42" or "1.5"
+    Format,
+    // s -> s as a number of the given type
+    Parse(NumericType)
+}
+
+
+fn string(value: &Value) -> Result<&str, InstructionError> {
+    match value {
+        Value::Str(string) => Ok(string),
+        _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type str"))
+    }
+}
+
+
+fn index(value: &Value) -> Result<usize, InstructionError> {
+    match value {
+        Value::Numeric(Numeric::USize(index)) => Ok(*index),
+        _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type usize"))
+    }
+}
+
+
+fn size(value: usize) -> Value {
+    Value::Numeric(Numeric::USize(value))
+}
+
+
+// The byte offset of char `index`, where the char length itself is the end of the string.
+fn byte_offset(string: &str, index: usize) -> Result<usize, InstructionError> {
+    string.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(iter::once(string.len()))
+        .nth(index)
+        .ok_or_else(|| InstructionError::new(ErrorKind::OutOfRange, &format!("Char index {} is past the end of the str", index)))
+}
+
+
+impl StrOp {
+    fn arity(&self) -> usize {
+        match self {
+            StrOp::Substring => 3,
+            StrOp::Len | StrOp::CharLen | StrOp::Trim | StrOp::Upper | StrOp::Lower | StrOp::Format | StrOp::Parse(_) => 1,
+            _ => 2
+        }
+    }
+
+    fn evaluate(&self, operands: &[Value]) -> Result<Vec<Value>, InstructionError> {
+        if let StrOp::Format = self {
+            return match &operands[0] {
+                Value::Numeric(numeric) => Ok(vec![Value::Str(numeric.digits())]),
+                _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type numeric"))
+            };
+        }
+
+        let a = string(&operands[0])?;
+
+        Ok(match self {
+            StrOp::Concat => vec![Value::Str(format!("{}{}", a, string(&operands[1])?))],
+            StrOp::Len => vec![size(a.len())],
+            StrOp::CharLen => vec![size(a.chars().count())],
+            StrOp::Substring => {
+                let (start, end) = (index(&operands[1])?, index(&operands[2])?);
+
+                if start > end {
+                    let message = format!("Substring start {} is after its end {}", start, end);
+                    return Err(InstructionError::new(ErrorKind::OutOfRange, &message));
+                }
+
+                vec![Value::Str(a[byte_offset(a, start)?..byte_offset(a, end)?].to_string())]
+            },
+            StrOp::IndexOf => match a.find(string(&operands[1])?) {
+                Some(offset) => vec![size(a[..offset].chars().count()), Value::Bool(true)],
+                None => vec![size(a.chars().count()), Value::Bool(false)]
+            },
+            StrOp::Split => {
+                let separator = string(&operands[1])?;
+
+                let pieces: Vec<Value> = if separator.is_empty() {
+                    a.chars().map(|c| Value::Str(c.to_string())).collect()
+                } else {
+                    a.split(separator).map(|piece| Value::Str(piece.to_string())).collect()
+                };
+
+                let count = pieces.len();
+                pieces.into_iter().chain(iter::once(size(count))).collect()
+            },
+            StrOp::Trim => vec![Value::Str(a.trim().to_string())],
+            StrOp::Upper => vec![Value::Str(a.to_uppercase())],
+            StrOp::Lower => vec![Value::Str(a.to_lowercase())],
+            StrOp::StartsWith => vec![Value::Bool(a.starts_with(string(&operands[1])?))],
+            StrOp::EndsWith => vec![Value::Bool(a.ends_with(string(&operands[1])?))],
+            StrOp::Eql => vec![Value::Bool(a == string(&operands[1])?)],
+            StrOp::GreaterThan => vec![Value::Bool(a > string(&operands[1])?)],
+            StrOp::LessThan => vec![Value::Bool(a < string(&operands[1])?)],
+            StrOp::GreaterThanEq => vec![Value::Bool(a >= string(&operands[1])?)],
+            StrOp::LessThanEq => vec![Value::Bool(a <= string(&operands[1])?)],
+            StrOp::Parse(to) => {
+                let parsed = to.parse(a).ok_or_else(|| {
+                    InstructionError::new(ErrorKind::InvalidNumber, &format!("{:?} is not a valid {}", a, to.suffix()))
+                })?;
+
+                vec![Value::Numeric(parsed)]
+            },
+            StrOp::Join | StrOp::Format => unreachable!()
+        })
+    }
+
+    fn join(current_stack: &mut Vec<Value>) -> InstructionResult {
+        let len = current_stack.len();
+
+        if len < 2 {
+            return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, "Join needs a count and a separator on the stack"));
+        }
+
+        let joined = index(&current_stack[len - 2]).and_then(|count| {
+            if len - 2 < count {
+                return Err(InstructionError::new(ErrorKind::StackUnderflow, &format!("Join needs {} pieces on the stack", count)));
+            }
+
+            let pieces = current_stack[len - 2 - count..len - 2].iter()
+                .map(string)
+                .collect::<Result<Vec<&str>, InstructionError>>()?;
+
+            Ok((count, pieces.join(string(&current_stack[len - 1])?)))
+        });
+
+        match joined {
+            Ok((count, joined)) => {
+                current_stack.truncate(len - 2 - count);
+                current_stack.push(Value::Str(joined));
+
+                InstructionResult::None
+            },
+            Err(error) => InstructionResult::Error(error)
+        }
+    }
+}
+
+
+impl Runnable for StrOp {
+    fn run(&self, stack: &mut Stack) -> InstructionResult {
+        let current_stack = stack.current();
+
+        let mut current_stack = current_stack.borrow_mut();
+
+        if let StrOp::Join = self {
+            return StrOp::join(&mut current_stack);
+        }
+
+        let len = current_stack.len();
+        let arity = self.arity();
+
+        if len < arity {
+            let message = format!("Str operation needs {} operand(s) on the stack", arity);
+            return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, &message));
+        }
+
+        match self.evaluate(&current_stack[len - arity..]) {
+            Ok(results) => {
+                current_stack.truncate(len - arity);
+                current_stack.extend(results);
+
+                InstructionResult::None
+            },
+            Err(error) => InstructionResult::Error(error)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::testing::{self, contents};
+
+    fn run(op: StrOp, operands: Vec<Value>) -> Result<Vec<String>, ErrorKind> {
+        let mut stack = Stack::new();
+        stack.current().borrow_mut().extend(operands);
+
+        testing::run(&mut stack, op).map(|_| contents(&stack))
+    }
+
+    fn str(string: &str) -> Value {
+        Value::Str(string.to_string())
+    }
+
+    #[test]
+    fn positions_count_chars() {
+        assert_eq!(run(StrOp::Len, vec![str("Wörld")]), Ok(vec!["6usize".to_string()]));
+        assert_eq!(run(StrOp::CharLen, vec![str("Wörld")]), Ok(vec!["5usize".to_string()]));
+        assert_eq!(run(StrOp::Substring, vec![str("Wörld"), size(1), size(3)]), Ok(vec!["\"ör\"".to_string()]));
+        assert_eq!(run(StrOp::Substring, vec![str("Wörld"), size(5), size(5)]), Ok(vec!["\"\"".to_string()]));
+        assert_eq!(run(StrOp::Substring, vec![str("Wörld"), size(4), size(6)]), Err(ErrorKind::OutOfRange));
+        assert_eq!(run(StrOp::IndexOf, vec![str("Wörld"), str("l")]), Ok(vec!["3usize".to_string(), "true".to_string()]));
+        assert_eq!(run(StrOp::IndexOf, vec![str("Wörld"), str("x")]), Ok(vec!["5usize".to_string(), "false".to_string()]));
+    }
+
+    #[test]
+    fn split_and_join_round_trip() {
+        let split = run(StrOp::Split, vec![str("a,b,,c"), str(",")]).unwrap();
+        assert_eq!(split, ["\"a\"", "\"b\"", "\"\"", "\"c\"", "4usize"]);
+
+        let pieces = vec![str("a"), str("b"), str(""), str("c"), size(4), str(",")];
+        assert_eq!(run(StrOp::Join, pieces), Ok(vec!["\"a,b,,c\"".to_string()]));
+
+        assert_eq!(run(StrOp::Join, vec![str("a"), size(2), str(",")]), Err(ErrorKind::StackUnderflow));
+    }
+
+    #[test]
+    fn format_and_parse_every_numeric_type() {
+        for numeric_type in [
+            NumericType::UInt8, NumericType::UInt16, NumericType::UInt32, NumericType::UInt64, NumericType::UInt128, NumericType::USize,
+            NumericType::Int8, NumericType::Int16, NumericType::Int32, NumericType::Int64, NumericType::Int128, NumericType::ISize,
+            NumericType::Float32, NumericType::Float64
+        ] {
+            let value = numeric_type.parse("100").unwrap();
+            let formatted = run(StrOp::Format, vec![Value::Numeric(value.clone())]).unwrap();
+            let digits = formatted[0].trim_matches('"');
+
+            assert_eq!(run(StrOp::Parse(numeric_type), vec![str(digits)]), Ok(vec![value.to_string()]));
+            assert_eq!(run(StrOp::Parse(numeric_type), vec![str("1x")]), Err(ErrorKind::InvalidNumber));
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::data_type::DataType;
+use crate::value::Value;
+use crate::instruction::InstructionError;
+use crate::error::ErrorKind;
+
+
+// A struct type declared by a program, such as `struct Point x: i32, y: i32`.
+#[derive(Debug, PartialEq)]
+pub struct StructDef {
+    pub name: String,
+    // In declaration order, which is also the order `struct.new` takes them off the stack.
+    pub fields: Vec<(String, DataType)>
+}
+
+
+// How `struct.get` and `struct.set` pick a field. Names are looked up when the op runs, since
+// the struct type isn't known until then.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Name(String),
+    Index(usize)
+}
+
+
+// An instance of a `StructDef`.
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub definition: Rc<StructDef>,
+    pub fields: Vec<Value>
+}
+
+
+impl StructDef {
+    pub fn field_index(&self, field: &Field) -> Result<usize, InstructionError> {
+        let index = match field {
+            Field::Name(name) => self.fields.iter().position(|(field_name, _)| field_name == name),
+            Field::Index(index) => Some(*index).filter(|index| *index < self.fields.len())
+        };
+
+        index.ok_or_else(|| InstructionError::new(ErrorKind::InvalidField, &format!("{} has no field {}", self.name, field)))
+    }
+}
+
+
+// Written the way the assembler reads them, without the leading `struct`.
+impl fmt::Display for StructDef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        let fields: Vec<String> = self.fields.iter().map(|(name, data_type)| format!("{}: {}", name, data_type)).collect();
+
+        if !fields.is_empty() {
+            write!(f, " {}", fields.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Field::Name(name) => write!(f, "{}", name),
+            Field::Index(index) => write!(f, "{}", index)
+        }
+    }
+}
+
+
+impl fmt::Display for Struct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields: Vec<String> = self.definition.fields.iter()
+            .zip(self.fields.iter())
+            .map(|((name, _), value)| format!("{}: {}", name, value))
+            .collect();
+
+        if fields.is_empty() {
+            write!(f, "{} {{}}", self.definition.name)
+        } else {
+            write!(f, "{} {{ {} }}", self.definition.name, fields.join(", "))
+        }
+    }
+}
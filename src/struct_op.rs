@@ -0,0 +1,188 @@
+use std::rc::Rc;
+use std::collections::HashMap;
+
+use crate::stack::Stack;
+use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
+use crate::data_type::Typed;
+use crate::value::Value;
+use crate::struct_def::{StructDef, Struct, Field};
+
+// `Get` and `Set` leave the struct where it was. Field values must have exactly their declared
+// type.
+#[derive(Debug, Clone)]
+pub enum StructOp {
+    // fields... -> a struct of the named type, the first field bottom-most
+    New(String),
+    // struct -> struct, the field's value
+    Get(Field),
+    // struct, value -> struct with the field set to value
+    Set(Field)
+}
+
+
+fn instance(value: &mut Value) -> Result<&mut Rc<Struct>, InstructionError> {
+    match value {
+        Value::Struct(instance) => Ok(instance),
+        _ => Err(InstructionError::new(ErrorKind::TypeMismatch, "Operand is not of type struct"))
+    }
+}
+
+
+fn check_field(definition: &StructDef, index: usize, value: &Value) -> Result<(), InstructionError> {
+    let (name, data_type) = &definition.fields[index];
+
+    if value.get_type() == *data_type {
+        Ok(())
+    } else {
+        let message = format!("Field {} of {} is of type {}, found {}", name, definition.name, data_type, value.get_type());
+        Err(InstructionError::new(ErrorKind::TypeMismatch, &message))
+    }
+}
+
+
+impl StructOp {
+    fn arity(&self, structs: &HashMap<String, Rc<StructDef>>) -> Result<usize, InstructionError> {
+        Ok(match self {
+            StructOp::New(name) => match structs.get(name) {
+                Some(definition) => definition.fields.len(),
+                None => return Err(InstructionError::new(ErrorKind::InvalidStructType, &format!("Unknown struct type {}", name)))
+            },
+            StructOp::Get(_) => 1,
+            StructOp::Set(_) => 2
+        })
+    }
+
+    // Everything is checked before the struct is changed, so a failed op can put its operands back.
+    fn evaluate(&self, operands: &mut [Value], structs: &HashMap<String, Rc<StructDef>>) -> Result<Vec<Value>, InstructionError> {
+        if let StructOp::New(name) = self {
+            let definition = &structs[name];
+
+            for (index, value) in operands.iter().enumerate() {
+                check_field(definition, index, value)?;
+            }
+
+            let instance = Struct { definition: definition.clone(), fields: operands.to_vec() };
+
+            return Ok(vec![Value::Struct(Rc::new(instance))]);
+        }
+
+        let (head, rest) = operands.split_first_mut().unwrap();
+        let instance = instance(head)?;
+
+        let results = match self {
+            StructOp::Get(field) => vec![instance.fields[instance.definition.field_index(field)?].clone()],
+            StructOp::Set(field) => {
+                let index = instance.definition.field_index(field)?;
+                check_field(&instance.definition, index, &rest[0])?;
+
+                Rc::make_mut(instance).fields[index] = rest[0].clone();
+                vec![]
+            },
+            StructOp::New(_) => unreachable!()
+        };
+
+        Ok([head.clone()].into_iter().chain(results).collect())
+    }
+
+    // `New` looks its type up in `structs`, the struct types of the running program.
+    pub fn run_with(&self, stack: &mut Stack, structs: &HashMap<String, Rc<StructDef>>) -> InstructionResult {
+        let current_stack = stack.current();
+
+        let mut current_stack = current_stack.borrow_mut();
+
+        let arity = match self.arity(structs) {
+            Ok(arity) => arity,
+            Err(error) => return InstructionResult::Error(error)
+        };
+
+        if current_stack.len() < arity {
+            let message = format!("Struct operation needs {} operand(s) on the stack", arity);
+            return InstructionResult::Error(InstructionError::new(ErrorKind::StackUnderflow, &message));
+        }
+
+        let start = current_stack.len() - arity;
+        let mut operands = current_stack.split_off(start);
+
+        match self.evaluate(&mut operands, structs) {
+            Ok(results) => {
+                current_stack.extend(results);
+
+                InstructionResult::None
+            },
+            Err(error) => {
+                current_stack.extend(operands);
+
+                InstructionResult::Error(error)
+            }
+        }
+    }
+}
+
+
+impl Runnable for StructOp {
+    fn run(&self, stack: &mut Stack) -> InstructionResult {
+        self.run_with(stack, &HashMap::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_type::DataType;
+    use crate::numeric::{Numeric, NumericType};
+    use crate::instruction::testing::{finished, push, contents};
+
+    fn structs() -> HashMap<String, Rc<StructDef>> {
+        let point = StructDef {
+            name: "Point".to_string(),
+            fields: vec![("x".to_string(), DataType::Numeric(NumericType::Int32)), ("y".to_string(), DataType::Bool)]
+        };
+
+        HashMap::from([("Point".to_string(), Rc::new(point))])
+    }
+
+    fn run(stack: &mut Stack, op: StructOp) -> Result<(), ErrorKind> {
+        finished(op.run_with(stack, &structs())).map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn fields_by_name_and_index() {
+        let mut stack = Stack::new();
+
+        push(&mut stack, Value::Numeric(Numeric::Int32(1)));
+        push(&mut stack, Value::Bool(false));
+        run(&mut stack, StructOp::New("Point".to_string())).unwrap();
+
+        push(&mut stack, Value::Bool(true));
+        run(&mut stack, StructOp::Set(Field::Index(1))).unwrap();
+        run(&mut stack, StructOp::Get(Field::Name("y".to_string()))).unwrap();
+
+        assert_eq!(contents(&stack), ["Point { x: 1i32, y: true }", "true"]);
+    }
+
+    #[test]
+    fn fields_keep_their_declared_types() {
+        let mut stack = Stack::new();
+
+        push(&mut stack, Value::Numeric(Numeric::Int64(1)));
+        push(&mut stack, Value::Bool(false));
+        assert_eq!(run(&mut stack, StructOp::New("Point".to_string())), Err(ErrorKind::TypeMismatch));
+        assert_eq!(run(&mut stack, StructOp::New("Line".to_string())), Err(ErrorKind::InvalidStructType));
+        assert_eq!(contents(&stack), ["1i64", "false"]);
+
+        let mut stack = Stack::new();
+
+        push(&mut stack, Value::Numeric(Numeric::Int32(1)));
+        push(&mut stack, Value::Bool(false));
+        run(&mut stack, StructOp::New("Point".to_string())).unwrap();
+
+        assert_eq!(run(&mut stack, StructOp::Get(Field::Name("z".to_string()))), Err(ErrorKind::InvalidField));
+        assert_eq!(run(&mut stack, StructOp::Get(Field::Index(2))), Err(ErrorKind::InvalidField));
+
+        push(&mut stack, Value::Numeric(Numeric::Int64(2)));
+        assert_eq!(run(&mut stack, StructOp::Set(Field::Name("x".to_string()))), Err(ErrorKind::TypeMismatch));
+        assert_eq!(contents(&stack), ["Point { x: 1i32, y: false }", "2i64"]);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::value::{Value, sorted_entries};
+use crate::instruction::Instruction;
+
+
+// Hooks called by `FunctionController` as it runs. The entry function itself has no call
+// events, only the functions it calls.
+pub trait Tracer {
+    fn before_instruction(&mut self, _function: usize, _index: usize, _instruction: &Instruction, _top: Option<&Value>) {}
+
+    fn after_instruction(&mut self, _function: usize, _index: usize, _instruction: &Instruction, _top: Option<&Value>) {}
+
+    // `carried` is how many values `Stack::substack` moved into the new frame.
+    fn call_entry(&mut self, _function: usize, _carried: usize) {}
+
+    // A tail call replaces the current function instead of entering a new frame, so it is
+    // not matched by a `call_exit` of its own. `carried` is how many values were kept.
+    fn tail_call(&mut self, _function: usize, _carried: usize) {}
+
+    // `carried` is how many values `Stack::destack` moved back to the caller.
+    fn call_exit(&mut self, _function: usize, _carried: usize) {}
+}
+
+
+fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+
+// Ptrs, nested ones included, are rendered without their address so traces of separate runs
+// can be diffed. Everything else reads as its `Display` form.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Ptr(_) => "ptr".to_string(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(text).collect();
+
+            format!("[{}]", items.join(", "))
+        },
+        Value::Map(map) => {
+            let entries: Vec<String> = sorted_entries(map).iter()
+                .map(|(key, value)| format!("{}: {}", key.to_value(), text(value)))
+                .collect();
+
+            format!("{{{}}}", entries.join(", "))
+        },
+        Value::Struct(instance) => {
+            let fields: Vec<String> = instance.definition.fields.iter()
+                .zip(instance.fields.iter())
+                .map(|((name, _), value)| format!("{}: {}", name, text(value)))
+                .collect();
+
+            if fields.is_empty() {
+                format!("{} {{}}", instance.definition.name)
+            } else {
+                format!("{} {{ {} }}", instance.definition.name, fields.join(", "))
+            }
+        },
+        value => value.to_string()
+    }
+}
+
+
+fn json_value(value: Option<&Value>) -> String {
+    match value {
+        Some(value) => json_string(&text(value)),
+        None => "null".to_string()
+    }
+}
+
+
+// Writes one JSON object per line. Instructions are rendered with the supplied assembly
+// lines (see `disassembler::function_lines`), falling back to their debug form.
+pub struct JsonTracer<W: Write> {
+    writer: W,
+    lines: HashMap<usize, Vec<String>>
+}
+
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(writer: W, lines: HashMap<usize, Vec<String>>) -> JsonTracer<W> {
+        JsonTracer { writer, lines }
+    }
+
+    fn instruction(&self, function: usize, index: usize, instruction: &Instruction) -> String {
+        self.lines.get(&function)
+            .and_then(|lines| lines.get(index))
+            .cloned()
+            .unwrap_or_else(|| format!("{:?}", instruction))
+    }
+
+    // Tracing must never stop the program, so write errors are ignored.
+    fn write_line(&mut self, line: String) {
+        let _ = writeln!(self.writer, "{}", line);
+    }
+
+    fn instruction_event(&mut self, event: &str, function: usize, index: usize, instruction: &Instruction, top: Option<&Value>) {
+        let line = format!(
+            "{{\"event\":\"{}\",\"fn\":{},\"index\":{},\"instruction\":{},\"top\":{}}}",
+            event,
+            function,
+            index,
+            json_string(&self.instruction(function, index, instruction)),
+            json_value(top)
+        );
+
+        self.write_line(line);
+    }
+}
+
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn before_instruction(&mut self, function: usize, index: usize, instruction: &Instruction, top: Option<&Value>) {
+        self.instruction_event("before", function, index, instruction, top);
+    }
+
+    fn after_instruction(&mut self, function: usize, index: usize, instruction: &Instruction, top: Option<&Value>) {
+        self.instruction_event("after", function, index, instruction, top);
+    }
+
+    fn call_entry(&mut self, function: usize, carried: usize) {
+        self.write_line(format!("{{\"event\":\"call\",\"fn\":{},\"carried\":{}}}", function, carried));
+    }
+
+    fn tail_call(&mut self, function: usize, carried: usize) {
+        self.write_line(format!("{{\"event\":\"tail_call\",\"fn\":{},\"carried\":{}}}", function, carried));
+    }
+
+    fn call_exit(&mut self, function: usize, carried: usize) {
+        self.write_line(format!("{{\"event\":\"return\",\"fn\":{},\"carried\":{}}}", function, carried));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use crate::ptr::Ptr;
+    use crate::numeric::Numeric;
+    use crate::function::testing::controller;
+
+    #[test]
+    fn strings_are_escaped_for_json() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("say \"hi\"\\"), "\"say \\\"hi\\\"\\\\\"");
+        assert_eq!(json_string("a\nb\r\tc"), "\"a\\nb\\r\\tc\"");
+        assert_eq!(json_string("\u{1}\u{1f}"), "\"\\u0001\\u001f\"");
+        assert_eq!(json_string("é"), "\"é\"");
+    }
+
+    #[test]
+    fn ptrs_have_no_address_at_any_depth() {
+        let ptr = Value::Ptr(Ptr::new(Value::Bool(true)));
+        let array = Value::Array(Rc::new(vec![ptr.clone(), Value::Numeric(Numeric::Int32(1))]));
+
+        assert_eq!(json_value(Some(&ptr)), "\"ptr\"");
+        assert_eq!(json_value(Some(&array)), "\"[ptr, 1i32]\"");
+        assert_eq!(json_value(Some(&Value::Array(Rc::new(vec![array])))), "\"[[ptr, 1i32]]\"");
+        assert_eq!(json_value(None), "null");
+    }
+
+    // A writer the test can still read after handing it to the controller.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // A call instruction's `after` comes before the call it makes.
+    #[test]
+    fn events_follow_calls_and_returns() {
+        let output = Shared::default();
+        let mut controller = controller("
+            fn main params=0 returns=0
+                push 1i32
+                call outer
+            end
+
+            fn outer params=1 returns=1
+                tailcall inner
+            end
+
+            fn inner params=1 returns=1
+            end
+        ");
+
+        controller.set_tracer(Box::new(JsonTracer::new(output.clone(), HashMap::new())));
+        controller.run().unwrap();
+
+        let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+        let events: Vec<String> = output.lines()
+            .map(|line| line.split(',').take(2).collect::<Vec<_>>().join(","))
+            .collect();
+
+        assert_eq!(events, [
+            "{\"event\":\"before\",\"fn\":1",
+            "{\"event\":\"after\",\"fn\":1",
+            "{\"event\":\"before\",\"fn\":1",
+            "{\"event\":\"after\",\"fn\":1",
+            "{\"event\":\"call\",\"fn\":2",
+            "{\"event\":\"before\",\"fn\":2",
+            "{\"event\":\"after\",\"fn\":2",
+            "{\"event\":\"tail_call\",\"fn\":3",
+            "{\"event\":\"return\",\"fn\":3",
+        ]);
+        assert!(output.lines().nth(1).unwrap().ends_with("\"top\":\"1i32\"}"), "{}", output);
+    }
+}
@@ -1,11 +1,21 @@
 use crate::stack::Stack;
 use crate::instruction::{Runnable, InstructionResult, InstructionError};
+use crate::error::ErrorKind;
 use crate::numeric::NumericType;
 use crate::value::Value;
+use crate::data_type::{DataType, Typed};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TypeOp {
-    NumericCast(NumericType)
+    NumericCast(NumericType),
+    // Fails with `OutOfRange` instead of wrapping, and on NaN to an integer type.
+    CheckedCast(NumericType),
+    // Clamps to the bounds of the type, NaN to an integer type still fails.
+    SaturatingCast(NumericType),
+    // Both leave the inspected value on the stack, so a program can branch on the result and
+    // still use the value.
+    TypeOf,
+    Is(DataType)
 }
 
 
@@ -16,15 +26,46 @@ impl Runnable for TypeOp {
         let mut current_stack = current_stack.borrow_mut();
 
         match self {
-            TypeOp::NumericCast(to) => {
-                let end = current_stack.pop().unwrap();
-
-                if let Value::Numeric(end) = end {
-                    current_stack.push(Value::Numeric(end.cast(to)));
-                } else {
-                    return InstructionResult::Error(
-                        InstructionError::new("Can't perform cast on non-numeric type")
-                    );
+            TypeOp::NumericCast(to) | TypeOp::CheckedCast(to) | TypeOp::SaturatingCast(to) => {
+                let end = match current_stack.last() {
+                    Some(Value::Numeric(end)) => end,
+                    Some(_) => {
+                        return InstructionResult::Error(
+                            InstructionError::new(ErrorKind::TypeMismatch, "Can't perform cast on non-numeric type")
+                        );
+                    },
+                    None => {
+                        return InstructionResult::Error(
+                            InstructionError::new(ErrorKind::StackUnderflow, "No item on stack to cast")
+                        );
+                    }
+                };
+
+                let result = match self {
+                    TypeOp::CheckedCast(_) => end.checked_cast(to),
+                    TypeOp::SaturatingCast(_) => end.saturating_cast(to),
+                    _ => Ok(end.clone().cast(to))
+                };
+
+                // The value is only replaced on success, so a failed cast leaves it on the stack.
+                match result {
+                    Ok(cast) => *current_stack.last_mut().unwrap() = Value::Numeric(cast),
+                    Err(error) => return InstructionResult::Error(error)
+                }
+            },
+            TypeOp::TypeOf | TypeOp::Is(_) => {
+                let data_type = match current_stack.last() {
+                    Some(value) => value.get_type(),
+                    None => {
+                        return InstructionResult::Error(
+                            InstructionError::new(ErrorKind::StackUnderflow, "No item on stack to inspect")
+                        );
+                    }
+                };
+
+                match self {
+                    TypeOp::Is(expected) => current_stack.push(Value::Bool(data_type == *expected)),
+                    _ => current_stack.push(Value::Type(data_type))
                 }
             }
         };
@@ -32,3 +73,59 @@ impl Runnable for TypeOp {
         InstructionResult::None
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numeric::Numeric;
+    use crate::instruction::testing::{run, push, contents};
+
+    fn cast(op: TypeOp, value: Numeric) -> Result<String, ErrorKind> {
+        let mut stack = Stack::new();
+        push(&mut stack, Value::Numeric(value));
+
+        let result = run(&mut stack, op);
+        assert_eq!(stack.current().borrow().len(), 1, "a cast replaces its value or leaves it");
+
+        result.map(|_| contents(&stack).remove(0))
+    }
+
+    #[test]
+    fn checked_casts() {
+        let checked = |value, to| cast(TypeOp::CheckedCast(to), value);
+
+        assert_eq!(checked(Numeric::Int32(300), NumericType::UInt8), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Int32(255), NumericType::UInt8), Ok("255u8".to_string()));
+        assert_eq!(checked(Numeric::Int8(-1), NumericType::UInt128), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::UInt128(u128::MAX), NumericType::Int128), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Float64(f64::NAN), NumericType::Int32), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Float64(-3.9), NumericType::Int8), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Float32(255.9), NumericType::UInt8), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Float64(-3.0), NumericType::Int8), Ok("-3i8".to_string()));
+        assert_eq!(checked(Numeric::Float32(2.5), NumericType::Float64), Ok("2.5f64".to_string()));
+        assert_eq!(checked(Numeric::Float64(256.0), NumericType::UInt8), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Float64(9223372036854775808.0), NumericType::Int64), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Float64(-9223372036854775808.0), NumericType::Int64), Ok(format!("{}i64", i64::MIN)));
+        assert_eq!(checked(Numeric::Float64(1e39), NumericType::Float32), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Float64(f64::INFINITY), NumericType::Float32), Ok("inff32".to_string()));
+        assert_eq!(checked(Numeric::UInt128(u128::MAX), NumericType::Float32), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Int64(16777217), NumericType::Float32), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Int64(16777216), NumericType::Float32), Ok("16777216.0f32".to_string()));
+        assert_eq!(checked(Numeric::Int64((1 << 53) + 1), NumericType::Float64), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Int128(i128::MAX), NumericType::Float64), Err(ErrorKind::OutOfRange));
+        assert_eq!(checked(Numeric::Int128(i128::MIN), NumericType::Float32).map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    fn saturating_casts() {
+        let saturating = |value, to| cast(TypeOp::SaturatingCast(to), value);
+
+        assert_eq!(saturating(Numeric::Int32(300), NumericType::UInt8), Ok("255u8".to_string()));
+        assert_eq!(saturating(Numeric::Int64(-5), NumericType::UInt32), Ok("0u32".to_string()));
+        assert_eq!(saturating(Numeric::UInt64(u64::MAX), NumericType::Int8), Ok("127i8".to_string()));
+        assert_eq!(saturating(Numeric::Float64(-1e30), NumericType::Int64), Ok(format!("{}i64", i64::MIN)));
+        assert_eq!(saturating(Numeric::Float64(1e40), NumericType::Float32), Ok(format!("{:?}f32", f32::MAX)));
+        assert_eq!(saturating(Numeric::Float64(f64::NAN), NumericType::UInt8), Err(ErrorKind::OutOfRange));
+    }
+}
@@ -1,14 +1,80 @@
-use crate::numeric::Numeric;
+use std::fmt;
+use std::rc::Rc;
+use std::collections::HashMap;
+
+use crate::numeric::{Numeric, NumericType};
 use crate::ptr::Ptr;
 use crate::data_type::{DataType, Typed};
+use crate::instruction::InstructionError;
+use crate::error::ErrorKind;
+use crate::struct_def::Struct;
+use crate::cast_to_value;
 
 
+// Arrays, maps and structs are values like any other: every copy behaves as its own, so `dup`
+// followed by a change leaves the original as it was. Copies share their contents until one of
+// them is changed, so a value that only lives in one place is updated in place.
 #[derive(Clone, Debug)]
 pub enum Value {
     Str(String),
     Numeric(Numeric),
     Bool(bool),
-    Ptr(Ptr)
+    Ptr(Ptr),
+    Type(DataType),
+    Array(Rc<Vec<Value>>),
+    Map(Rc<HashMap<MapKey, Value>>),
+    Struct(Rc<Struct>)
+}
+
+
+// The values that can key a map. Floats are left out since NaN isn't equal to itself. Like
+// `Value::equals`, integers of different types are different keys, so `1i32` and `1i64` don't
+// collide. Integers are kept as their bits, which is exact for every integer type.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MapKey {
+    Str(String),
+    Bool(bool),
+    Integer(NumericType, u128)
+}
+
+
+impl MapKey {
+    pub fn from_value(value: &Value) -> Result<MapKey, InstructionError> {
+        match value {
+            Value::Str(string) => Ok(MapKey::Str(string.clone())),
+            Value::Bool(boolean) => Ok(MapKey::Bool(*boolean)),
+            Value::Numeric(numeric) if numeric.is_float() => {
+                Err(InstructionError::new(ErrorKind::TypeMismatch, "Floats can't be used as map keys"))
+            },
+            Value::Numeric(numeric) => {
+                let numeric_type = numeric.numeric_type();
+                let numeric = numeric.clone();
+
+                Ok(MapKey::Integer(numeric_type, cast_to_value!(numeric, i128) as u128))
+            },
+            other => Err(InstructionError::new(ErrorKind::TypeMismatch, &format!("A {} can't be used as a map key", other.get_type())))
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Str(string) => Value::Str(string.clone()),
+            MapKey::Bool(boolean) => Value::Bool(*boolean),
+            MapKey::Integer(numeric_type, bits) if numeric_type.is_signed() => {
+                Value::Numeric(Numeric::Int128(*bits as i128).cast(numeric_type))
+            },
+            MapKey::Integer(numeric_type, bits) => Value::Numeric(Numeric::UInt128(*bits).cast(numeric_type))
+        }
+    }
+}
+
+
+// Map entries sorted by key, so iterating and printing a map doesn't depend on hashing.
+pub fn sorted_entries(map: &HashMap<MapKey, Value>) -> Vec<(&MapKey, &Value)> {
+    let mut entries: Vec<(&MapKey, &Value)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    entries
 }
 
 
@@ -16,9 +82,74 @@ impl Typed for Value {
     fn get_type(&self) -> DataType {
         match self {
             Value::Str(_) => DataType::Str,
-            Value::Numeric(_) => DataType::Int32,
+            Value::Numeric(numeric) => DataType::Numeric(numeric.numeric_type()),
             Value::Bool(_) => DataType::Bool,
-            Value::Ptr(_) => DataType::Ptr
+            Value::Ptr(_) => DataType::Ptr,
+            Value::Type(_) => DataType::Type,
+            Value::Array(_) => DataType::Array,
+            Value::Map(_) => DataType::Map,
+            Value::Struct(_) => DataType::Struct
+        }
+    }
+}
+
+
+impl Value {
+    // Values of different types are never equal, including numerics of different widths.
+    // Ptrs are equal when they are the same ptr, not when they hold equal values.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Numeric(a), Value::Numeric(b)) => matches!(a.eq(b), Some(Value::Bool(true))),
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Ptr(a), Value::Ptr(b)) => a.address() == b.address(),
+            (Value::Type(a), Value::Type(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.equals(b)),
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len() && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| a.equals(b)))
+            },
+            (Value::Struct(a), Value::Struct(b)) => {
+                a.definition == b.definition && a.fields.iter().zip(b.fields.iter()).all(|(a, b)| a.equals(b))
+            },
+            _ => false
+        }
+    }
+
+    // Adds the ptrs held directly by this value, looking inside arrays, maps and structs but not
+    // through the ptrs themselves.
+    pub fn collect_ptrs(&self, ptrs: &mut Vec<Ptr>) {
+        match self {
+            Value::Ptr(ptr) => ptrs.push(ptr.clone()),
+            Value::Array(items) => items.iter().for_each(|item| item.collect_ptrs(ptrs)),
+            Value::Map(map) => sorted_entries(map).into_iter().for_each(|(_, item)| item.collect_ptrs(ptrs)),
+            Value::Struct(instance) => instance.fields.iter().for_each(|field| field.collect_ptrs(ptrs)),
+            Value::Str(_) | Value::Numeric(_) | Value::Bool(_) | Value::Type(_) => {}
+        }
+    }
+}
+
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(string) => write!(f, "{:?}", string),
+            Value::Numeric(numeric) => write!(f, "{}", numeric),
+            Value::Bool(boolean) => write!(f, "{}", boolean),
+            Value::Ptr(ptr) => write!(f, "ptr({:#x})", ptr.address()),
+            Value::Type(data_type) => write!(f, "#{}", data_type),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+
+                write!(f, "[{}]", items.join(", "))
+            },
+            Value::Map(map) => {
+                let entries: Vec<String> = sorted_entries(map).iter()
+                    .map(|(key, value)| format!("{}: {}", key.to_value(), value))
+                    .collect();
+
+                write!(f, "{{{}}}", entries.join(", "))
+            },
+            Value::Struct(instance) => write!(f, "{}", instance)
         }
     }
 }
@@ -29,6 +160,8 @@ pub enum ValueType {
     Ptr(Ptr),
     Value(Value),
     StackValue,
+    // A local of the running call, see `Instruction::with_locals`.
+    Local(usize),
 }
 
 impl ValueType {
@@ -36,7 +169,9 @@ impl ValueType {
         match self {
             ValueType::Ptr(ptr) => ptr.value.try_borrow().ok().map(|value| value.clone()),
             ValueType::Value(value) => Some(value.clone()),
-            ValueType::StackValue => last_stack_value.cloned()
+            ValueType::StackValue => last_stack_value.cloned(),
+            // Locals are swapped for their ptrs before the instruction runs.
+            ValueType::Local(_) => None
         }
     }
 }